@@ -1,12 +1,33 @@
-use seating_system::{SeatingArea, Visibility};
+use seating_system::render::Renderer;
+use seating_system::{SeatingArea, StabilizeOutcome, Visibility};
 use std::env;
 use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
 
 fn main() {
-    let filename = env::args()
-        .skip(1)
-        .next()
-        .unwrap_or_else(|| "input".to_owned());
+    let mut args = env::args().skip(1);
+    let filename = args.next().unwrap_or_else(|| "input".to_owned());
+    // Optionally `--animate` in the terminal, write `--frames <directory>`
+    // or write the per round `--history <csv file>`
+    let mut renderer = None;
+    let mut history_file = None;
+    match args.next().as_deref() {
+        Some("--animate") => {
+            renderer = Some(Renderer::Terminal {
+                delay: Duration::from_millis(200),
+            })
+        }
+        Some("--frames") => {
+            renderer = Some(Renderer::PpmSequence {
+                directory: PathBuf::from(args.next().expect("Missing frame directory.")),
+                scale: 4,
+            })
+        }
+        Some("--history") => history_file = Some(args.next().expect("Missing history file.")),
+        Some(other) => panic!("Unknown argument: {}", other),
+        None => {}
+    }
     let s = fs::read_to_string(filename).expect("Coulnd't read file.");
     let mut area: SeatingArea = s.parse().expect("Couldn't parse input.");
 
@@ -15,10 +36,33 @@ fn main() {
     // Part two
     let visibility = Visibility::Far;
 
-    let rounds = area.stabilize(visibility);
-    println!(
-        "{} seats are occupied after stabilizing which took {} rounds",
-        area.occupied(),
-        rounds
-    );
+    let outcome = if let Some(renderer) = renderer {
+        renderer
+            .animate(&mut area, visibility, None)
+            .expect("Couldn't render rounds.")
+    } else if let Some(history_file) = history_file {
+        let (outcome, history) = area.stabilize_with_history(visibility, None);
+        let mut out = fs::File::create(history_file).expect("Couldn't create history file.");
+        history
+            .write_csv(&mut out)
+            .expect("Couldn't write history file.");
+        outcome
+    } else {
+        area.stabilize(visibility)
+    };
+
+    match outcome {
+        StabilizeOutcome::FixedPoint { rounds } => println!(
+            "{} seats are occupied after stabilizing which took {} rounds",
+            area.occupied(),
+            rounds
+        ),
+        StabilizeOutcome::Cycle { start, period } => println!(
+            "Seating never stabilizes, it repeats every {} rounds starting at round {}",
+            period, start
+        ),
+        StabilizeOutcome::RoundLimit { rounds } => {
+            println!("Seating didn't stabilize within {} rounds", rounds)
+        }
+    }
 }
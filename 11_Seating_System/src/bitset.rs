@@ -0,0 +1,289 @@
+use crate::cycle::Fingerprints;
+use crate::{Boundary, ParseError, Seat, SeatingArea, StabilizeOutcome};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+const WORD_BITS: usize = 64;
+
+// Seating area for `Visibility::Adjacent` that keeps every row as a bitset,
+// column `j` of a row is bit `j % 64` of word `j / 64`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BitSeatingArea {
+    height: usize,
+    width: usize,
+    words_per_row: usize,
+    // Set for every position that is a seat and not floor
+    seats: Vec<u64>,
+    occupied: Vec<u64>,
+}
+
+// Bit sliced neighbor counts, saturating at four
+#[derive(Clone, Copy, Default)]
+struct Counts {
+    ones: u64,
+    twos: u64,
+    fours: u64,
+}
+
+impl Counts {
+    fn add(&mut self, neighbors: u64) {
+        let carry = self.ones & neighbors;
+        self.ones ^= neighbors;
+        let carry_twos = self.twos & carry;
+        self.twos ^= carry;
+        self.fours |= carry_twos;
+    }
+
+    fn zero(&self) -> u64 {
+        !(self.ones | self.twos | self.fours)
+    }
+}
+
+impl BitSeatingArea {
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> Option<Seat> {
+        if row >= self.height || col >= self.width {
+            return None;
+        }
+        let index = row * self.words_per_row + col / WORD_BITS;
+        let bit = 1 << (col % WORD_BITS);
+        Some(if self.occupied[index] & bit != 0 {
+            Seat::Occupied
+        } else if self.seats[index] & bit != 0 {
+            Seat::Free
+        } else {
+            Seat::Floor
+        })
+    }
+
+    pub fn occupied(&self) -> usize {
+        self.occupied.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    // Applies a single round and returns whether anything changed
+    pub fn step(&mut self) -> bool {
+        let mut buffer = vec![0; self.occupied.len()];
+        self.step_with(&mut buffer)
+    }
+
+    // Same as `step`, but reuses `buffer` instead of allocating a new bitset every round
+    fn step_with(&mut self, buffer: &mut Vec<u64>) -> bool {
+        buffer.resize(self.occupied.len(), 0);
+        // Stands in for the rows above the first and below the last row
+        let floor = vec![0; self.words_per_row];
+        for row in 0..self.height {
+            self.apply_round_to_row(row, &floor, buffer);
+        }
+        std::mem::swap(&mut self.occupied, buffer);
+        self.occupied != *buffer
+    }
+
+    pub fn stabilize(&mut self) -> StabilizeOutcome {
+        self.run_until_stable(None)
+    }
+
+    pub fn stabilize_limited(&mut self, max_rounds: usize) -> StabilizeOutcome {
+        self.run_until_stable(Some(max_rounds))
+    }
+
+    // Large layouts do oscillate, so the rounds are remembered by a fingerprint of the
+    // occupied seats. Unlike `SeatingArea` this trusts a matching fingerprint without
+    // replaying the rounds, which would double the work for a large layout.
+    fn run_until_stable(&mut self, max_rounds: Option<usize>) -> StabilizeOutcome {
+        let mut seen = Fingerprints::new();
+        seen.record(&self.occupied, 0);
+        let mut buffer = Vec::new();
+        let mut rounds = 0;
+        loop {
+            if max_rounds.is_some_and(|max| rounds >= max) {
+                return StabilizeOutcome::RoundLimit { rounds };
+            }
+            rounds += 1;
+            if !self.step_with(&mut buffer) {
+                return StabilizeOutcome::FixedPoint { rounds };
+            }
+            if let Some(start) = seen.record(&self.occupied, rounds) {
+                return StabilizeOutcome::Cycle {
+                    start,
+                    period: rounds - start,
+                };
+            }
+        }
+    }
+
+    fn apply_round_to_row(&self, row: usize, floor: &[u64], new_occupied: &mut [u64]) {
+        let n = self.words_per_row;
+        let row_words = |r: usize| &self.occupied[r * n..(r + 1) * n];
+        let above = if row > 0 { row_words(row - 1) } else { floor };
+        let current = row_words(row);
+        let below = if row + 1 < self.height {
+            row_words(row + 1)
+        } else {
+            floor
+        };
+
+        // Occupied seats to the left and to the right of every column
+        let sideways = |words: &[u64], k: usize| {
+            let word = words[k];
+            let previous = if k > 0 { words[k - 1] } else { 0 };
+            let next = if k + 1 < n { words[k + 1] } else { 0 };
+            (
+                (word << 1) | (previous >> (WORD_BITS - 1)),
+                (word >> 1) | (next << (WORD_BITS - 1)),
+            )
+        };
+
+        for k in 0..n {
+            let mut counts = Counts::default();
+            for words in [above, current, below] {
+                let (left, right) = sideways(words, k);
+                counts.add(left);
+                counts.add(right);
+            }
+            counts.add(above[k]);
+            counts.add(below[k]);
+
+            let index = row * n + k;
+            let seats = self.seats[index];
+            let occupied = self.occupied[index];
+            // `fours` is set where the walk away limit of four neighbors is reached
+            new_occupied[index] =
+                seats & ((!occupied & counts.zero()) | (occupied & !counts.fours));
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum UnsupportedLayout {
+    // Only floor, free and occupied seats can be packed into bits
+    Seat { row: usize, col: usize, seat: Seat },
+    Boundary(Boundary),
+}
+
+impl fmt::Display for UnsupportedLayout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UnsupportedLayout::Seat { row, col, seat } => write!(
+                f,
+                "seat {:?} in row {}, column {} is not supported",
+                seat, row, col
+            ),
+            UnsupportedLayout::Boundary(boundary) => {
+                write!(f, "boundary {:?} is not supported", boundary)
+            }
+        }
+    }
+}
+
+impl Error for UnsupportedLayout {}
+
+impl TryFrom<&SeatingArea> for BitSeatingArea {
+    type Error = UnsupportedLayout;
+
+    fn try_from(area: &SeatingArea) -> Result<Self, Self::Error> {
+        if area.boundary() != Boundary::Empty {
+            return Err(UnsupportedLayout::Boundary(area.boundary()));
+        }
+
+        let (height, width) = (area.height(), area.width());
+        let words_per_row = width.div_ceil(WORD_BITS);
+        let mut seats = vec![0; height * words_per_row];
+        let mut occupied = vec![0; height * words_per_row];
+
+        for ((row, col), seat) in area.iter() {
+            let index = row * words_per_row + col / WORD_BITS;
+            let bit = 1 << (col % WORD_BITS);
+            match seat {
+                Seat::Floor => {}
+                Seat::Free => seats[index] |= bit,
+                Seat::Occupied => {
+                    seats[index] |= bit;
+                    occupied[index] |= bit;
+                }
+                seat => return Err(UnsupportedLayout::Seat { row, col, seat }),
+            }
+        }
+
+        Ok(Self {
+            height,
+            width,
+            words_per_row,
+            seats,
+            occupied,
+        })
+    }
+}
+
+// Same format as `SeatingArea`, but only floor, free and occupied seats are valid. Packs
+// the seats right away, so a large layout never exists as a `SeatingArea`.
+impl FromStr for BitSeatingArea {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lines: Vec<&str> = s.strip_suffix("\n").unwrap_or(s).split("\n").collect();
+        let height = lines.len();
+        let width = lines[0].chars().count();
+        let words_per_row = width.div_ceil(WORD_BITS);
+        let mut seats = vec![0; height * words_per_row];
+        let mut occupied = vec![0; height * words_per_row];
+
+        for (row, line) in lines.iter().enumerate() {
+            let mut found = 0;
+            for (col, c) in line.chars().enumerate() {
+                found += 1;
+                if col >= width {
+                    continue;
+                }
+                let index = row * words_per_row + col / WORD_BITS;
+                let bit = 1 << (col % WORD_BITS);
+                match c {
+                    '.' => {}
+                    'L' => seats[index] |= bit,
+                    '#' => {
+                        seats[index] |= bit;
+                        occupied[index] |= bit;
+                    }
+                    found => return Err(ParseError::InvalidSeat { row, col, found }),
+                }
+            }
+            if found != width {
+                return Err(ParseError::RaggedRow {
+                    row,
+                    expected: width,
+                    found,
+                });
+            }
+        }
+
+        Ok(Self {
+            height,
+            width,
+            words_per_row,
+            seats,
+            occupied,
+        })
+    }
+}
+
+impl From<&BitSeatingArea> for SeatingArea {
+    fn from(area: &BitSeatingArea) -> Self {
+        let mut result = SeatingArea::filled(area.height, area.width, Seat::Floor);
+        for row in 0..area.height {
+            for col in 0..area.width {
+                if let Some(seat) = area.get(row, col) {
+                    result.set(row, col, seat);
+                }
+            }
+        }
+        result
+    }
+}
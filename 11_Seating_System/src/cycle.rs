@@ -0,0 +1,29 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+// Remembers the round of every state by a 64-bit fingerprint instead of keeping the
+// states themselves, so a long simulation of a large layout stays cheap
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Fingerprints {
+    rounds: HashMap<u64, usize>,
+}
+
+impl Fingerprints {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    // Records the state after `round` and returns the latest earlier round whose state
+    // had the same fingerprint
+    pub(crate) fn record<T: Hash + ?Sized>(&mut self, state: &T, round: usize) -> Option<usize> {
+        let mut hasher = DefaultHasher::new();
+        state.hash(&mut hasher);
+        self.rounds.insert(hasher.finish(), round)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.rounds.len()
+    }
+}
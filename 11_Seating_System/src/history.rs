@@ -0,0 +1,93 @@
+use crate::{Seat, SeatingArea, StabilizeOutcome, Visibility};
+use std::io::{self, Write};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RoundStats {
+    pub round: usize,
+    pub occupied: usize,
+    pub became_occupied: usize,
+    pub became_free: usize,
+    // Average number of occupied seats an occupied seat can see
+    pub local_density: f64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct History {
+    pub rounds: Vec<RoundStats>,
+}
+
+impl History {
+    pub fn write_csv<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(
+            out,
+            "round,occupied,became_occupied,became_free,local_density"
+        )?;
+        for stats in &self.rounds {
+            writeln!(
+                out,
+                "{},{},{},{},{}",
+                stats.round,
+                stats.occupied,
+                stats.became_occupied,
+                stats.became_free,
+                stats.local_density
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl SeatingArea {
+    pub fn stabilize_with_history(
+        &mut self,
+        visibility: Visibility,
+        max_rounds: Option<usize>,
+    ) -> (StabilizeOutcome, History) {
+        let mut history = History::default();
+        let mut previous: Option<Vec<Vec<Seat>>> = None;
+
+        let outcome = self.stabilize_observed(visibility, max_rounds, |round, area| {
+            let (mut became_occupied, mut became_free) = (0, 0);
+            if let Some(previous) = &previous {
+                for (old_row, new_row) in previous.iter().zip(&area.seats) {
+                    for (&old, &new) in old_row.iter().zip(new_row) {
+                        match (old.is_occupied(), new.is_occupied()) {
+                            (false, true) => became_occupied += 1,
+                            (true, false) => became_free += 1,
+                            _ => {}
+                        }
+                    }
+                }
+            }
+
+            history.rounds.push(RoundStats {
+                round,
+                occupied: area.occupied(),
+                became_occupied,
+                became_free,
+                local_density: area.local_density(visibility),
+            });
+            previous = Some(area.seats.clone());
+        });
+
+        (outcome, history)
+    }
+
+    fn local_density(&self, visibility: Visibility) -> f64 {
+        let mut occupied = 0;
+        let mut neighbors = 0;
+        for (i, row) in self.seats.iter().enumerate() {
+            for (j, &seat) in row.iter().enumerate() {
+                if seat.is_occupied() {
+                    occupied += 1;
+                    neighbors += self.adjacent_to(i, j, visibility);
+                }
+            }
+        }
+        if occupied == 0 {
+            0.0
+        } else {
+            neighbors as f64 / occupied as f64
+        }
+    }
+}
@@ -1,11 +1,86 @@
+use cycle::Fingerprints;
 use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
 use std::str::FromStr;
 
+pub mod bitset;
+mod cycle;
+pub mod history;
+pub mod render;
+
 #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
 pub enum Seat {
     Floor,
     Free,
     Occupied,
+    // Always occupied
+    Reserved,
+    // Never occupied, but still blocks the view like any other seat
+    Blocked,
+    // Counts `weight` times towards its neighbors while occupied
+    Weighted { weight: Weight, occupied: bool },
+}
+
+// Weight of a weighted seat, only 1 to 9 can be written as a single character
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct Weight(u8);
+
+impl Weight {
+    pub fn new(weight: u8) -> Option<Self> {
+        if (1..=9).contains(&weight) {
+            Some(Self(weight))
+        } else {
+            None
+        }
+    }
+
+    pub fn get(self) -> u8 {
+        self.0
+    }
+}
+
+impl Seat {
+    pub fn is_occupied(self) -> bool {
+        matches!(
+            self,
+            Seat::Occupied | Seat::Reserved | Seat::Weighted { occupied: true, .. }
+        )
+    }
+
+    // How much this seat adds to the occupied count of its neighbors
+    pub fn weight(self) -> usize {
+        match self {
+            Seat::Occupied | Seat::Reserved => 1,
+            Seat::Weighted {
+                weight,
+                occupied: true,
+            } => weight.get() as usize,
+            _ => 0,
+        }
+    }
+
+    fn occupy(self) -> Self {
+        match self {
+            Seat::Free => Seat::Occupied,
+            Seat::Weighted { weight, .. } => Seat::Weighted {
+                weight,
+                occupied: true,
+            },
+            seat => seat,
+        }
+    }
+
+    fn vacate(self) -> Self {
+        match self {
+            Seat::Occupied => Seat::Free,
+            Seat::Weighted { weight, .. } => Seat::Weighted {
+                weight,
+                occupied: false,
+            },
+            seat => seat,
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
@@ -14,34 +89,180 @@ pub enum Visibility {
     Far,
 }
 
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq)]
+pub enum Boundary {
+    // Everything outside the grid is floor
+    #[default]
+    Empty,
+    // Leaving the grid on one side re-enters it on the opposite side
+    Wrap,
+    // Everything outside the grid counts as an occupied seat
+    Walls,
+    // The edges reflect, so looking past an edge looks back into the grid
+    Mirror,
+}
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum StabilizeOutcome {
+    // A round changed nothing, `rounds` includes that last round
+    FixedPoint { rounds: usize },
+    // The state after `start + period` rounds equals the state after `start` rounds
+    Cycle { start: usize, period: usize },
+    // Gave up after `rounds` rounds without settling
+    RoundLimit { rounds: usize },
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct SeatingArea {
     seats: Vec<Vec<Seat>>,
+    boundary: Boundary,
 }
 
 impl SeatingArea {
+    pub fn filled(height: usize, width: usize, seat: Seat) -> Self {
+        Self {
+            seats: vec![vec![seat; width]; height],
+            boundary: Boundary::default(),
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        self.seats.len()
+    }
+
+    pub fn width(&self) -> usize {
+        self.seats.first().map_or(0, Vec::len)
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> Option<Seat> {
+        self.seats.get(row)?.get(col).copied()
+    }
+
+    // Returns the previous seat, or `None` without changing anything if out of bounds
+    pub fn set(&mut self, row: usize, col: usize, seat: Seat) -> Option<Seat> {
+        let old = self.seats.get_mut(row)?.get_mut(col)?;
+        Some(std::mem::replace(old, seat))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = ((usize, usize), Seat)> + '_ {
+        self.seats.iter().enumerate().flat_map(|(i, row)| {
+            row.iter()
+                .copied()
+                .enumerate()
+                .map(move |(j, seat)| ((i, j), seat))
+        })
+    }
+
+    // Occupied neighbors of a position, weighted seats count with their weight.
+    // `None` if the position is out of bounds.
+    pub fn neighbors(&self, row: usize, col: usize, visibility: Visibility) -> Option<usize> {
+        self.get(row, col)?;
+        Some(self.adjacent_to(row, col, visibility))
+    }
+
+    // Applies a single round and returns whether anything changed
+    pub fn step(&mut self, visibility: Visibility) -> bool {
+        let mut new_seats = self.seats.clone();
+        self.apply_round_into(&mut new_seats, visibility);
+        let changed = new_seats != self.seats;
+        self.seats = new_seats;
+        changed
+    }
+
+    pub fn with_boundary(mut self, boundary: Boundary) -> Self {
+        self.boundary = boundary;
+        self
+    }
+
+    pub fn boundary(&self) -> Boundary {
+        self.boundary
+    }
+
     pub fn occupied(&self) -> usize {
         self.seats
             .iter()
-            .map(|row| row.iter().filter(|&&s| s == Seat::Occupied).count())
+            .map(|row| row.iter().filter(|s| s.is_occupied()).count())
             .sum()
     }
 
-    pub fn stabilize(&mut self, visibility: Visibility) -> usize {
+    pub fn stabilize(&mut self, visibility: Visibility) -> StabilizeOutcome {
+        self.stabilize_observed(visibility, None, |_, _| {})
+    }
+
+    pub fn stabilize_limited(
+        &mut self,
+        visibility: Visibility,
+        max_rounds: usize,
+    ) -> StabilizeOutcome {
+        self.stabilize_observed(visibility, Some(max_rounds), |_, _| {})
+    }
+
+    // Calls `observe` with the initial layout as round 0 and after every round that changed it
+    pub fn stabilize_observed<O>(
+        &mut self,
+        visibility: Visibility,
+        max_rounds: Option<usize>,
+        observe: O,
+    ) -> StabilizeOutcome
+    where
+        O: FnMut(usize, &SeatingArea),
+    {
+        self.run_until_stable(
+            max_rounds,
+            |area, new_seats| area.apply_round_into(new_seats, visibility),
+            observe,
+        )
+    }
+
+    fn run_until_stable<F, O>(
+        &mut self,
+        max_rounds: Option<usize>,
+        mut round: F,
+        mut observe: O,
+    ) -> StabilizeOutcome
+    where
+        F: FnMut(&Self, &mut [Vec<Seat>]),
+        O: FnMut(usize, &SeatingArea),
+    {
+        observe(0, self);
+        let initial = self.seats.clone();
         let mut new_seats = self.seats.clone();
+        let mut seen = Fingerprints::new();
+        seen.record(&self.seats, 0);
         let mut rounds = 0;
         loop {
+            if max_rounds.is_some_and(|max| rounds >= max) {
+                return StabilizeOutcome::RoundLimit { rounds };
+            }
             rounds += 1;
-            self.apply_round_into(&mut new_seats, visibility);
+            round(self, &mut new_seats);
             if new_seats == self.seats {
-                break;
+                return StabilizeOutcome::FixedPoint { rounds };
             }
             self.seats.clone_from(&new_seats);
+            observe(rounds, self);
+            if let Some(start) = seen.record(&self.seats, rounds) {
+                // Equal fingerprints only make a cycle likely, replaying the rounds up to
+                // `start` tells for sure
+                let mut replay = Self {
+                    seats: initial.clone(),
+                    boundary: self.boundary,
+                };
+                for _ in 0..start {
+                    round(&replay, &mut new_seats);
+                    std::mem::swap(&mut replay.seats, &mut new_seats);
+                }
+                if replay.seats == self.seats {
+                    return StabilizeOutcome::Cycle {
+                        start,
+                        period: rounds - start,
+                    };
+                }
+            }
         }
-        rounds
     }
 
-    fn apply_round_into(&self, new_seats: &mut Vec<Vec<Seat>>, visibility: Visibility) {
+    fn apply_round_into(&self, new_seats: &mut [Vec<Seat>], visibility: Visibility) {
         let walk_away_limit = match visibility {
             Visibility::Adjacent => 4,
             Visibility::Far => 5,
@@ -49,10 +270,10 @@ impl SeatingArea {
         for (i, row) in self.seats.iter().enumerate() {
             for (j, &seat) in row.iter().enumerate() {
                 let adjacent = self.adjacent_to(i, j, visibility);
-                new_seats[i][j] = if seat == Seat::Free && adjacent == 0 {
-                    Seat::Occupied
-                } else if seat == Seat::Occupied && adjacent >= walk_away_limit {
-                    Seat::Free
+                new_seats[i][j] = if !seat.is_occupied() && adjacent == 0 {
+                    seat.occupy()
+                } else if seat.is_occupied() && adjacent >= walk_away_limit {
+                    seat.vacate()
                 } else {
                     seat
                 };
@@ -65,23 +286,26 @@ impl SeatingArea {
 
         if visibility == Visibility::Adjacent {
             for dx in -1..=1 {
-                if let Some(row) = self.seats.get((row as isize + dx) as usize) {
-                    for dy in -1..=1 {
-                        if !(dx == 0 && dy == 0)
-                            && row.get((col as isize + dy) as usize) == Some(&Seat::Occupied)
-                        {
-                            c += 1;
-                        }
+                for dy in -1..=1 {
+                    if dx == 0 && dy == 0 {
+                        continue;
                     }
+                    c += match self.resolve(row as isize + dx, col as isize + dy) {
+                        // A seat never counts itself, even if the boundary leads back to it
+                        Some(position) if position == (row, col) => 0,
+                        Some(position) => self.seat(position).weight(),
+                        None if self.boundary == Boundary::Walls => 1,
+                        None => 0,
+                    };
                 }
             }
         } else if visibility == Visibility::Far {
             for dx in -1..=1 {
                 for dy in -1..=1 {
                     if dx != 0 || dy != 0 {
-                        if self.sees_occupied_in_direction_from_position(row, col, dx, dy) {
-                            c += 1;
-                        }
+                        c += self
+                            .seat_in_direction_from_position(row, col, dx, dy)
+                            .map_or(0, Seat::weight);
                     }
                 }
             }
@@ -91,66 +315,233 @@ impl SeatingArea {
         c
     }
 
-    fn sees_occupied_in_direction_from_position(
+    // The first seat that is visible in the given direction
+    fn seat_in_direction_from_position(
         &self,
         row: usize,
         col: usize,
-        dx: isize,
-        dy: isize,
-    ) -> bool {
+        mut dx: isize,
+        mut dy: isize,
+    ) -> Option<Seat> {
         let mut x = col as isize;
         let mut y = row as isize;
+        let start_direction = (dx, dy);
 
         loop {
             x += dx;
             y += dy;
 
-            if let Some(row) = self.seats.get(y as usize) {
-                if let Some(seat) = row.get(x as usize) {
-                    match seat {
-                        Seat::Occupied => return true,
-                        Seat::Free => return false,
-                        Seat::Floor => continue,
+            if self.boundary == Boundary::Mirror {
+                // Bounce off the edge, the edge seat is seen again on the way back
+                if !(0..self.height() as isize).contains(&y) {
+                    dy = -dy;
+                    y += dy;
+                }
+                if !(0..self.width() as isize).contains(&x) {
+                    dx = -dx;
+                    x += dx;
+                }
+            }
+
+            if let Some((r, c)) = self.resolve(y, x) {
+                if (r, c) == (row, col) {
+                    if (dx, dy) == start_direction {
+                        // Wrapping and mirroring always lead back here eventually
+                        return None;
                     }
-                } else {
-                    // Column out of bounds, no occupied seats coming anymore
-                    return false;
+                    // Looking past ourselves in a mirror
+                    continue;
                 }
+                y = r as isize;
+                x = c as isize;
+                match self.seat((r, c)) {
+                    Seat::Floor => continue,
+                    seat => return Some(seat),
+                }
+            } else if self.boundary == Boundary::Walls {
+                return Some(Seat::Occupied);
             } else {
-                // Row out of bounds, no occupied seats coming anymore
-                return false;
+                // Out of bounds, no seats coming anymore
+                return None;
             }
         }
     }
+
+    // Maps a position to the seat it refers to under the boundary mode,
+    // `None` means it is outside of the grid
+    fn resolve(&self, row: isize, col: isize) -> Option<(usize, usize)> {
+        let (height, width) = (self.height() as isize, self.width() as isize);
+        if height == 0 || width == 0 {
+            return None;
+        }
+        let reflect = |i: isize, len: isize| {
+            let i = i.rem_euclid(2 * len);
+            if i < len {
+                i
+            } else {
+                2 * len - i - 1
+            }
+        };
+        let (row, col) = match self.boundary {
+            Boundary::Empty | Boundary::Walls => (row, col),
+            Boundary::Wrap => (row.rem_euclid(height), col.rem_euclid(width)),
+            Boundary::Mirror => (reflect(row, height), reflect(col, width)),
+        };
+        if (0..height).contains(&row) && (0..width).contains(&col) {
+            Some((row as usize, col as usize))
+        } else {
+            None
+        }
+    }
+
+    fn seat(&self, (row, col): (usize, usize)) -> Seat {
+        self.seats[row][col]
+    }
 }
 
 impl FromStr for SeatingArea {
-    type Err = <Seat as TryFrom<char>>::Error;
+    type Err = ParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let seats = s
-            .strip_suffix("\n")
-            .unwrap_or(s)
-            .split("\n")
-            .map(|line| {
-                line.chars()
-                    .map(Seat::try_from)
-                    .collect::<Result<Vec<_>, _>>()
-            })
-            .collect::<Result<Vec<Vec<_>>, _>>()?;
-        Ok(Self { seats })
+        let seats =
+            s.strip_suffix("\n")
+                .unwrap_or(s)
+                .split("\n")
+                .enumerate()
+                .map(|(row, line)| {
+                    line.chars()
+                        .enumerate()
+                        .map(|(col, c)| {
+                            Seat::try_from(c).map_err(|InvalidSeat(found)| {
+                                ParseError::InvalidSeat { row, col, found }
+                            })
+                        })
+                        .collect::<Result<Vec<_>, _>>()
+                })
+                .collect::<Result<Vec<Vec<_>>, _>>()?;
+
+        if let Some(first) = seats.first() {
+            if let Some((row, ragged)) = seats
+                .iter()
+                .enumerate()
+                .find(|(_, row)| row.len() != first.len())
+            {
+                return Err(ParseError::RaggedRow {
+                    row,
+                    expected: first.len(),
+                    found: ragged.len(),
+                });
+            }
+        }
+
+        Ok(Self {
+            seats,
+            boundary: Boundary::default(),
+        })
+    }
+}
+
+// Rows and columns are counted from zero, like everywhere else in this crate
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum ParseError {
+    InvalidSeat {
+        row: usize,
+        col: usize,
+        found: char,
+    },
+    RaggedRow {
+        row: usize,
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::InvalidSeat { row, col, found } => {
+                write!(f, "invalid seat {:?} in row {}, column {}", found, row, col)
+            }
+            ParseError::RaggedRow {
+                row,
+                expected,
+                found,
+            } => write!(
+                f,
+                "row {} has {} seats, but the first row has {}",
+                row, found, expected
+            ),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct InvalidSeat(pub char);
+
+impl fmt::Display for InvalidSeat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid seat {:?}", self.0)
+    }
+}
+
+impl Error for InvalidSeat {}
+
+impl fmt::Display for SeatingArea {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, row) in self.seats.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            for &seat in row {
+                write!(f, "{}", char::from(seat))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Weighted seats are written as their weight `1`-`9` while free and as `A`-`I` while occupied
+impl From<Seat> for char {
+    fn from(seat: Seat) -> Self {
+        match seat {
+            Seat::Floor => '.',
+            Seat::Free => 'L',
+            Seat::Occupied => '#',
+            Seat::Reserved => 'R',
+            Seat::Blocked => 'X',
+            Seat::Weighted {
+                weight,
+                occupied: false,
+            } => (b'0' + weight.get()) as char,
+            Seat::Weighted {
+                weight,
+                occupied: true,
+            } => (b'A' + weight.get() - 1) as char,
+        }
     }
 }
 
 impl TryFrom<char> for Seat {
-    type Error = ();
+    type Error = InvalidSeat;
 
     fn try_from(c: char) -> Result<Self, Self::Error> {
         match c {
             '.' => Ok(Seat::Floor),
             'L' => Ok(Seat::Free),
             '#' => Ok(Seat::Occupied),
-            _ => Err(()),
+            'R' => Ok(Seat::Reserved),
+            'X' => Ok(Seat::Blocked),
+            '1'..='9' => Ok(Seat::Weighted {
+                weight: Weight(c as u8 - b'0'),
+                occupied: false,
+            }),
+            'A'..='I' => Ok(Seat::Weighted {
+                weight: Weight(c as u8 - b'A' + 1),
+                occupied: true,
+            }),
+            _ => Err(InvalidSeat(c)),
         }
     }
 }
@@ -159,7 +550,7 @@ impl TryFrom<char> for Seat {
 mod tests {
     use super::*;
 
-    const SMALL_EXAMPLE: &'static str = include_str!("../small");
+    const SMALL_EXAMPLE: &str = include_str!("../small");
 
     #[test]
     fn p1_small() {
@@ -178,4 +569,398 @@ mod tests {
         area.stabilize(Visibility::Far);
         assert_eq!(area.occupied(), 26);
     }
+
+    #[test]
+    fn display_roundtrip() {
+        let area = SMALL_EXAMPLE
+            .parse::<SeatingArea>()
+            .expect("Couldn't parse small example");
+        assert_eq!(area.to_string(), SMALL_EXAMPLE.trim_end());
+        assert_eq!(area.to_string().parse::<SeatingArea>(), Ok(area));
+    }
+
+    #[test]
+    fn stabilize_outcome_small() {
+        let mut area = SMALL_EXAMPLE
+            .parse::<SeatingArea>()
+            .expect("Couldn't parse small example");
+        assert_eq!(
+            area.stabilize(Visibility::Adjacent),
+            StabilizeOutcome::FixedPoint { rounds: 6 }
+        );
+    }
+
+    #[test]
+    fn round_limit() {
+        let mut area = SMALL_EXAMPLE
+            .parse::<SeatingArea>()
+            .expect("Couldn't parse small example");
+        assert_eq!(
+            area.stabilize_limited(Visibility::Far, 3),
+            StabilizeOutcome::RoundLimit { rounds: 3 }
+        );
+    }
+
+    #[test]
+    fn detects_oscillation() {
+        let mut area = "L.L".parse::<SeatingArea>().unwrap();
+        // Every seat flips each round, so the layout never settles
+        let outcome = area.run_until_stable(
+            Some(100),
+            |area, new_seats| {
+                for (new_row, row) in new_seats.iter_mut().zip(&area.seats) {
+                    for (new_seat, &seat) in new_row.iter_mut().zip(row) {
+                        *new_seat = if seat.is_occupied() {
+                            seat.vacate()
+                        } else {
+                            seat.occupy()
+                        };
+                    }
+                }
+            },
+            |_, _| {},
+        );
+        assert_eq!(
+            outcome,
+            StabilizeOutcome::Cycle {
+                start: 0,
+                period: 2
+            }
+        );
+    }
+
+    #[test]
+    fn detects_long_cycles() {
+        // Counts in binary with occupied seats as ones, wrapping from 1023 to 1
+        let mut area = "LLLLLLLLLL".parse::<SeatingArea>().unwrap();
+        let outcome = area.run_until_stable(
+            None,
+            |area, new_seats| {
+                let count = area.seats[0]
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, seat)| seat.is_occupied())
+                    .map(|(bit, _)| 1 << bit)
+                    .sum::<usize>();
+                let next = if count == 1023 { 1 } else { count + 1 };
+                for (bit, new_seat) in new_seats[0].iter_mut().enumerate() {
+                    *new_seat = if next & (1 << bit) != 0 {
+                        Seat::Occupied
+                    } else {
+                        Seat::Free
+                    };
+                }
+            },
+            |_, _| {},
+        );
+        assert_eq!(
+            outcome,
+            StabilizeOutcome::Cycle {
+                start: 1,
+                period: 1023
+            }
+        );
+    }
+
+    #[test]
+    fn cycle_detection_keeps_only_fingerprints() {
+        // A thousand of these states would take 800 MB, their fingerprints 16 KB
+        let mut seen = cycle::Fingerprints::new();
+        let mut state = vec![0u64; 100_000];
+        for round in 0..1000 {
+            state[round] = 1;
+            assert_eq!(seen.record(&state, round), None);
+        }
+        assert_eq!(seen.len(), 1000);
+        assert_eq!(seen.record(&state, 1000), Some(999));
+    }
+
+    #[test]
+    fn walls_boundary() {
+        let mut area = "LLL\nLLL\nLLL"
+            .parse::<SeatingArea>()
+            .unwrap()
+            .with_boundary(Boundary::Walls);
+        area.stabilize(Visibility::Adjacent);
+        assert_eq!(area.occupied(), 1);
+    }
+
+    #[test]
+    fn wrap_boundary_oscillates() {
+        // On a torus every seat of a full 3x3 grid is adjacent to all others
+        let mut area = "LLL\nLLL\nLLL"
+            .parse::<SeatingArea>()
+            .unwrap()
+            .with_boundary(Boundary::Wrap);
+        assert_eq!(
+            area.stabilize(Visibility::Adjacent),
+            StabilizeOutcome::Cycle {
+                start: 0,
+                period: 2
+            }
+        );
+    }
+
+    #[test]
+    fn mirror_boundary() {
+        let area = "..#L"
+            .parse::<SeatingArea>()
+            .unwrap()
+            .with_boundary(Boundary::Mirror);
+        assert_eq!(
+            area.seat_in_direction_from_position(0, 3, -1, 0),
+            Some(Seat::Occupied)
+        );
+        // Looking right bounces back off the right edge, past the seat itself
+        assert_eq!(
+            area.seat_in_direction_from_position(0, 3, 1, 0),
+            Some(Seat::Occupied)
+        );
+        assert_eq!(area.seat_in_direction_from_position(0, 3, 0, -1), None);
+        assert_eq!(area.adjacent_to(0, 3, Visibility::Far), 6);
+        assert_eq!(area.adjacent_to(0, 3, Visibility::Adjacent), 3);
+    }
+
+    #[test]
+    fn ppm_frame() {
+        let area = "#.\nLL".parse::<SeatingArea>().unwrap();
+        let mut out = Vec::new();
+        render::write_ppm(&mut out, &area, 2).unwrap();
+        let header = b"P6\n4 4\n255\n";
+        assert_eq!(&out[..header.len()], header);
+        assert_eq!(out.len(), header.len() + 4 * 4 * 3);
+        assert_eq!(&out[header.len()..header.len() + 3], &[220, 50, 47]);
+    }
+
+    #[test]
+    fn history_small() {
+        let mut area = SMALL_EXAMPLE
+            .parse::<SeatingArea>()
+            .expect("Couldn't parse small example");
+        let (outcome, history) = area.stabilize_with_history(Visibility::Adjacent, None);
+        assert_eq!(outcome, StabilizeOutcome::FixedPoint { rounds: 6 });
+        assert_eq!(history.rounds.len(), 6);
+        assert_eq!(history.rounds[0].occupied, 0);
+        assert_eq!(history.rounds[1].became_occupied, 71);
+        assert_eq!(history.rounds[5].occupied, 37);
+        for pair in history.rounds.windows(2) {
+            let (before, after) = (&pair[0], &pair[1]);
+            assert_eq!(
+                before.occupied + after.became_occupied - after.became_free,
+                after.occupied
+            );
+        }
+
+        let mut csv = Vec::new();
+        history.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert_eq!(csv.lines().count(), 7);
+        assert!(csv.starts_with("round,occupied,"));
+    }
+
+    #[test]
+    fn special_seats() {
+        let mut area = "RL.X\nL.2L".parse::<SeatingArea>().unwrap();
+        assert_eq!(area.to_string(), "RL.X\nL.2L");
+        assert_eq!(area.occupied(), 1);
+
+        area.stabilize(Visibility::Adjacent);
+        // The reserved seat keeps its neighbors away, the blocked seat stays empty
+        assert_eq!(area.to_string(), "RL.X\nL.B#");
+        assert_eq!(area.occupied(), 3);
+    }
+
+    #[test]
+    fn weighted_seats_count_their_weight() {
+        let area = "L4\n.#".parse::<SeatingArea>().unwrap();
+        assert_eq!(area.adjacent_to(0, 0, Visibility::Adjacent), 1);
+        let area = "LD\n.#".parse::<SeatingArea>().unwrap();
+        assert_eq!(area.adjacent_to(0, 0, Visibility::Adjacent), 5);
+        assert_eq!(area.adjacent_to(0, 0, Visibility::Far), 5);
+
+        assert_eq!(Weight::new(0), None);
+        assert_eq!(Weight::new(10), None);
+        assert_eq!(Weight::new(220), None);
+        for weight in 1..=9 {
+            for &occupied in &[false, true] {
+                let seat = Seat::Weighted {
+                    weight: Weight::new(weight).unwrap(),
+                    occupied,
+                };
+                assert_eq!(Seat::try_from(char::from(seat)), Ok(seat));
+                assert_eq!(seat.weight(), if occupied { weight as usize } else { 0 });
+            }
+        }
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(
+            "L.L\nL?L".parse::<SeatingArea>(),
+            Err(ParseError::InvalidSeat {
+                row: 1,
+                col: 1,
+                found: '?'
+            })
+        );
+        assert_eq!(
+            "L.L\nL.L\nLL\n".parse::<SeatingArea>(),
+            Err(ParseError::RaggedRow {
+                row: 2,
+                expected: 3,
+                found: 2
+            })
+        );
+        assert_eq!(Seat::try_from('x'), Err(InvalidSeat('x')));
+    }
+
+    #[test]
+    fn query_and_mutate() {
+        let mut area = SeatingArea::filled(2, 3, Seat::Free);
+        assert_eq!((area.height(), area.width()), (2, 3));
+        assert_eq!(area.set(0, 1, Seat::Occupied), Some(Seat::Free));
+        assert_eq!(area.set(2, 0, Seat::Occupied), None);
+        assert_eq!(area.get(0, 1), Some(Seat::Occupied));
+        assert_eq!(area.get(0, 3), None);
+        assert_eq!(area.to_string(), "L#L\nLLL");
+        assert_eq!(
+            area.iter()
+                .filter(|(_, seat)| seat.is_occupied())
+                .collect::<Vec<_>>(),
+            vec![((0, 1), Seat::Occupied)]
+        );
+        assert_eq!(area.neighbors(1, 1, Visibility::Adjacent), Some(1));
+        assert_eq!(area.neighbors(1, 1, Visibility::Far), Some(1));
+        assert_eq!(area.neighbors(2, 0, Visibility::Adjacent), None);
+
+        // Rays from outside the grid would never find their way back over the floor
+        let area = "..".parse::<SeatingArea>().unwrap();
+        for &boundary in &[
+            Boundary::Empty,
+            Boundary::Wrap,
+            Boundary::Walls,
+            Boundary::Mirror,
+        ] {
+            let area = area.clone().with_boundary(boundary);
+            assert_eq!(area.neighbors(5, 5, Visibility::Far), None);
+            assert_eq!(area.neighbors(0, 2, Visibility::Far), None);
+        }
+    }
+
+    #[test]
+    fn step_matches_stabilize() {
+        let mut stepped = SMALL_EXAMPLE
+            .parse::<SeatingArea>()
+            .expect("Couldn't parse small example");
+        let mut stabilized = stepped.clone();
+
+        let mut rounds = 1;
+        while stepped.step(Visibility::Far) {
+            rounds += 1;
+        }
+        assert_eq!(
+            stabilized.stabilize(Visibility::Far),
+            StabilizeOutcome::FixedPoint { rounds }
+        );
+        assert_eq!(stepped, stabilized);
+    }
+
+    #[test]
+    fn bitset_small() {
+        let area = SMALL_EXAMPLE
+            .parse::<SeatingArea>()
+            .expect("Couldn't parse small example");
+        let mut bits = bitset::BitSeatingArea::try_from(&area).unwrap();
+        assert_eq!(bits.stabilize(), StabilizeOutcome::FixedPoint { rounds: 6 });
+        assert_eq!(bits.occupied(), 37);
+    }
+
+    // Reproducible layout from a small linear congruential generator,
+    // every fifth position or so is floor and the rest are free seats
+    fn random_area(height: usize, width: usize, seed: u64) -> SeatingArea {
+        let mut state = seed;
+        let mut area = SeatingArea::filled(height, width, Seat::Free);
+        for row in 0..height {
+            for col in 0..width {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                if (state >> 33).is_multiple_of(5) {
+                    area.set(row, col, Seat::Floor);
+                }
+            }
+        }
+        area
+    }
+
+    #[test]
+    fn bitset_matches_seating_area() {
+        for &(height, width) in &[(1, 1), (3, 63), (5, 64), (7, 65), (20, 130), (33, 200)] {
+            let mut area = random_area(height, width, 0x2020_1211);
+            let mut bits = bitset::BitSeatingArea::try_from(&area).unwrap();
+            assert_eq!(SeatingArea::from(&bits), area);
+            loop {
+                let changed = area.step(Visibility::Adjacent);
+                assert_eq!(bits.step(), changed);
+                assert_eq!(SeatingArea::from(&bits), area);
+                if !changed {
+                    break;
+                }
+            }
+            assert_eq!(bits.occupied(), area.occupied());
+        }
+    }
+
+    #[test]
+    fn bitset_rejects_special_layouts() {
+        let area = "L.R".parse::<SeatingArea>().unwrap();
+        assert_eq!(
+            bitset::BitSeatingArea::try_from(&area),
+            Err(bitset::UnsupportedLayout::Seat {
+                row: 0,
+                col: 2,
+                seat: Seat::Reserved
+            })
+        );
+        let area = area.with_boundary(Boundary::Wrap);
+        assert_eq!(
+            bitset::BitSeatingArea::try_from(&area),
+            Err(bitset::UnsupportedLayout::Boundary(Boundary::Wrap))
+        );
+    }
+
+    #[test]
+    fn bitset_parses_like_seating_area() {
+        let mut layouts = vec![SMALL_EXAMPLE.to_owned(), "#.L\n".to_owned()];
+        layouts.push(random_area(20, 130, 3).to_string());
+        for layout in layouts {
+            let bits = layout.parse::<bitset::BitSeatingArea>().unwrap();
+            assert_eq!(SeatingArea::from(&bits), layout.parse().unwrap());
+        }
+
+        for layout in ["L.L\nL.", "L.L\nL.LL", "L.x"] {
+            assert_eq!(
+                layout.parse::<bitset::BitSeatingArea>(),
+                Err(layout.parse::<SeatingArea>().unwrap_err())
+            );
+        }
+        assert_eq!(
+            "L.R".parse::<bitset::BitSeatingArea>(),
+            Err(ParseError::InvalidSeat {
+                row: 0,
+                col: 2,
+                found: 'R'
+            })
+        );
+    }
+
+    #[test]
+    fn bitset_detects_oscillation() {
+        // Larger layouts can end up flipping between two states forever
+        let mut area = random_area(35, 35, 1);
+        let mut bits = bitset::BitSeatingArea::try_from(&area).unwrap();
+        let outcome = area.stabilize(Visibility::Adjacent);
+        assert!(matches!(outcome, StabilizeOutcome::Cycle { period: 2, .. }));
+        assert_eq!(bits.stabilize(), outcome);
+    }
 }
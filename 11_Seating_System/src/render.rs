@@ -0,0 +1,105 @@
+use crate::{Seat, SeatingArea, StabilizeOutcome, Visibility};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Renderer {
+    // Redraws the terminal for every round, waiting `delay` after each frame
+    Terminal { delay: Duration },
+    // Writes `round_0000.ppm`, `round_0001.ppm`, ... into `directory`,
+    // every seat is drawn as a `scale` x `scale` square
+    PpmSequence { directory: PathBuf, scale: usize },
+}
+
+impl Renderer {
+    pub fn animate(
+        &self,
+        area: &mut SeatingArea,
+        visibility: Visibility,
+        max_rounds: Option<usize>,
+    ) -> io::Result<StabilizeOutcome> {
+        let mut result = Ok(());
+        let outcome = area.stabilize_observed(visibility, max_rounds, |round, area| {
+            if result.is_ok() {
+                result = self.render_frame(round, area);
+            }
+        });
+        result.map(|_| outcome)
+    }
+
+    pub fn render_frame(&self, round: usize, area: &SeatingArea) -> io::Result<()> {
+        match self {
+            Renderer::Terminal { delay } => {
+                let stdout = io::stdout();
+                let mut out = stdout.lock();
+                // Clear the screen and move the cursor to the top left
+                write!(out, "\x1b[2J\x1b[H")?;
+                write_ansi_frame(&mut out, round, area)?;
+                out.flush()?;
+                thread::sleep(*delay);
+                Ok(())
+            }
+            Renderer::PpmSequence { directory, scale } => {
+                let path = directory.join(format!("round_{:04}.ppm", round));
+                let mut out = BufWriter::new(File::create(path)?);
+                write_ppm(&mut out, area, *scale)?;
+                out.flush()
+            }
+        }
+    }
+}
+
+pub fn write_ansi_frame<W: Write>(out: &mut W, round: usize, area: &SeatingArea) -> io::Result<()> {
+    writeln!(out, "Round {}, {} occupied", round, area.occupied())?;
+    for row in &area.seats {
+        for &seat in row {
+            let color = match seat {
+                Seat::Floor => "90",
+                Seat::Free => "32",
+                Seat::Occupied => "31",
+                Seat::Reserved => "35",
+                Seat::Blocked => "34",
+                Seat::Weighted {
+                    occupied: false, ..
+                } => "33",
+                Seat::Weighted { occupied: true, .. } => "91",
+            };
+            write!(out, "\x1b[{}m{}", color, char::from(seat))?;
+        }
+        writeln!(out, "\x1b[0m")?;
+    }
+    Ok(())
+}
+
+pub fn write_ppm<W: Write>(out: &mut W, area: &SeatingArea, scale: usize) -> io::Result<()> {
+    let height = area.height();
+    let width = area.width();
+    write!(out, "P6\n{} {}\n255\n", width * scale, height * scale)?;
+
+    for row in &area.seats {
+        let mut line = Vec::with_capacity(width * scale * 3);
+        for &seat in row {
+            let color: [u8; 3] = match seat {
+                Seat::Floor => [40, 40, 40],
+                Seat::Free => [60, 180, 75],
+                Seat::Occupied => [220, 50, 47],
+                Seat::Reserved => [170, 60, 200],
+                Seat::Blocked => [40, 60, 140],
+                Seat::Weighted {
+                    occupied: false, ..
+                } => [200, 180, 40],
+                Seat::Weighted { occupied: true, .. } => [255, 110, 60],
+            };
+            for _ in 0..scale {
+                line.extend_from_slice(&color);
+            }
+        }
+        for _ in 0..scale {
+            out.write_all(&line)?;
+        }
+    }
+    Ok(())
+}
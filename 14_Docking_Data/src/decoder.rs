@@ -0,0 +1,38 @@
+use crate::symbolic::AddressCube;
+use crate::{Mask, Reg};
+
+// A decoder chip decides what a `mem[address] = value` instruction writes under the
+// current mask: the value that is stored and the cube of addresses it is stored at
+pub trait Decoder<const N: usize> {
+    fn decode_value(&self, mask: &Mask<N>, value: u64) -> u64;
+
+    fn decode_address(&self, mask: &Mask<N>, address: u64) -> AddressCube;
+}
+
+// Masks the value and leaves the address alone
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct DecoderV1;
+
+impl<const N: usize> Decoder<N> for DecoderV1 {
+    fn decode_value(&self, mask: &Mask<N>, value: u64) -> u64 {
+        Reg::<N>::new(mask.apply(value)).get()
+    }
+
+    fn decode_address(&self, _mask: &Mask<N>, address: u64) -> AddressCube {
+        AddressCube::new(0, address)
+    }
+}
+
+// Leaves the value alone and writes to every address the floating bits can produce
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct DecoderV2;
+
+impl<const N: usize> Decoder<N> for DecoderV2 {
+    fn decode_value(&self, _mask: &Mask<N>, value: u64) -> u64 {
+        value
+    }
+
+    fn decode_address(&self, mask: &Mask<N>, address: u64) -> AddressCube {
+        mask.address_cube(address)
+    }
+}
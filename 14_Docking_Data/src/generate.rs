@@ -0,0 +1,89 @@
+use crate::{InitializationProgram, Instruction, Mask, MaskBit};
+use std::convert::TryInto;
+
+// What a randomly generated program looks like. Addresses are drawn from the lowest
+// `address_bits` bits only, so that a small number of them makes writes collide.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ProgramShape {
+    pub masks: usize,
+    pub writes_per_mask: usize,
+    pub floating_bits: usize,
+    pub address_bits: usize,
+}
+
+impl Default for ProgramShape {
+    fn default() -> Self {
+        Self {
+            masks: 10,
+            writes_per_mask: 5,
+            floating_bits: 4,
+            address_bits: 8,
+        }
+    }
+}
+
+// Linear congruential generator, only its upper bits are random enough to be handed out
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        self.0 >> 32
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+
+    // A random number with `count` bits
+    fn bits(&mut self, count: usize) -> u64 {
+        if count == 0 {
+            return 0;
+        }
+        let value = (self.next() << 32) | self.next();
+        value >> (64 - count)
+    }
+}
+
+impl<const N: usize> InitializationProgram<N> {
+    // The same seed and shape always give the same program
+    pub fn random(seed: u64, shape: ProgramShape) -> Self {
+        assert!(
+            shape.floating_bits <= N,
+            "more floating bits than the mask has"
+        );
+        let mut rng = Lcg(seed);
+        let mut this = Self::new();
+
+        for _ in 0..shape.masks {
+            let mut bits: Vec<MaskBit> = (0..N)
+                .map(|_| match rng.next() & 1 {
+                    0 => MaskBit::Unset,
+                    _ => MaskBit::Set,
+                })
+                .collect();
+            // Partial Fisher-Yates shuffle, the first positions end up floating
+            let mut positions: Vec<usize> = (0..N).collect();
+            for i in 0..shape.floating_bits {
+                let j = i + rng.below(N - i);
+                positions.swap(i, j);
+                bits[positions[i]] = MaskBit::Leave;
+            }
+            let bits: [MaskBit; N] = bits.as_slice().try_into().unwrap();
+            this.instructions
+                .push(Instruction::SetMask(Mask::from(bits)));
+
+            for _ in 0..shape.writes_per_mask {
+                let address = rng.bits(shape.address_bits.min(N)) as usize;
+                let value = rng.bits(N);
+                this.instructions
+                    .push(Instruction::SetMemory { address, value });
+            }
+        }
+
+        this
+    }
+}
@@ -1,8 +1,24 @@
 use std::collections::HashMap;
 use std::convert::TryFrom;
-use std::convert::TryInto;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead};
 use std::str::FromStr;
 
+pub mod decoder;
+pub mod generate;
+pub mod machine;
+pub mod mask;
+pub mod memory;
+pub mod symbolic;
+pub mod trace;
+pub mod transform;
+
+use decoder::{Decoder, DecoderV1, DecoderV2};
+use machine::Machine;
+pub use mask::Mask;
+use memory::Memory;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum MaskBit {
     Set,
@@ -22,19 +38,35 @@ impl TryFrom<char> for MaskBit {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
-pub struct Reg(u64);
+impl From<MaskBit> for char {
+    fn from(bit: MaskBit) -> Self {
+        match bit {
+            MaskBit::Set => '1',
+            MaskBit::Unset => '0',
+            MaskBit::Leave => 'X',
+        }
+    }
+}
+
+// Width of the registers and masks of the puzzle's docking computer,
+// other widths up to 64 bits can be chosen through the const parameters
 pub const REG_SIZE: usize = 36;
 
-impl Reg {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Reg<const N: usize = REG_SIZE>(u64);
+
+impl<const N: usize> Reg<N> {
+    const VALID_SIZE: () = assert!(N > 0 && N <= 64, "registers hold 1 to 64 bits");
+
     pub fn new(val: u64) -> Self {
         let mut this: Self = Default::default();
         this.set(val);
         this
     }
 
-    fn size_mask() -> u64 {
-        (0..REG_SIZE).fold(0, |acc, _| (acc << 1) | 1)
+    pub fn size_mask() -> u64 {
+        let _: () = Self::VALID_SIZE;
+        u64::MAX >> (64 - N)
     }
 
     pub fn set(&mut self, val: u64) {
@@ -48,133 +80,757 @@ impl Reg {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-enum Instruction {
-    SetMask([MaskBit; REG_SIZE]),
+pub enum Instruction<const N: usize = REG_SIZE> {
+    SetMask(Mask<N>),
     SetMemory { address: usize, value: u64 },
 }
 
-impl FromStr for Instruction {
-    type Err = ();
+impl<const N: usize> FromStr for Instruction<N> {
+    type Err = Reason;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut iter = s.split(" = ");
-        let left = iter.next().ok_or(())?;
-        let right = iter.next().ok_or(())?;
+        let (left, right) = s.split_once(" = ").ok_or(Reason::MissingAssignment)?;
 
         if left == "mask" {
-            let val = right
-                .chars()
-                .map(MaskBit::try_from)
-                .collect::<Result<Vec<_>, _>>()?;
-            let val: [MaskBit; REG_SIZE] = val.as_slice().try_into().map_err(|_| ())?;
-
-            Ok(Instruction::SetMask(val))
-        } else if &left[0..3] == "mem" {
-            if left.as_bytes()[3] == b'[' && left.as_bytes()[left.len() - 1] == b']' {
-                let address: usize = left[4..left.len() - 1].parse().map_err(|_| ())?;
-                let value: u64 = right.parse().map_err(|_| ())?;
-                Ok(Instruction::SetMemory { address, value })
-            } else {
-                Err(())
-            }
+            let mask = right
+                .parse()
+                .map_err(|_| Reason::InvalidMask(right.to_owned()))?;
+            Ok(Instruction::SetMask(mask))
+        } else if let Some(address) = left.strip_prefix("mem[").and_then(|l| l.strip_suffix(']')) {
+            // Neither may be wider than the registers
+            let fits = |n: u64| n & !Reg::<N>::size_mask() == 0;
+            let address: usize = address
+                .parse()
+                .ok()
+                .filter(|&a| fits(a as u64))
+                .ok_or_else(|| Reason::InvalidAddress(address.to_owned()))?;
+            let value: u64 = right
+                .parse()
+                .ok()
+                .filter(|&v| fits(v))
+                .ok_or_else(|| Reason::InvalidValue(right.to_owned()))?;
+            Ok(Instruction::SetMemory { address, value })
         } else {
-            Err(())
+            Err(Reason::UnknownTarget(left.to_owned()))
+        }
+    }
+}
+
+impl<const N: usize> fmt::Display for Instruction<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::SetMask(mask) => write!(f, "mask = {}", mask),
+            Instruction::SetMemory { address, value } => write!(f, "mem[{}] = {}", address, value),
+        }
+    }
+}
+
+// Lines are counted from one, like in any text editor
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ParseError {
+    pub line: usize,
+    pub reason: Reason,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Reason {
+    MissingAssignment,
+    UnknownTarget(String),
+    InvalidMask(String),
+    InvalidAddress(String),
+    InvalidValue(String),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.reason)
+    }
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reason::MissingAssignment => write!(f, "expected `<target> = <value>`"),
+            Reason::UnknownTarget(target) => write!(f, "unknown target {:?}", target),
+            Reason::InvalidMask(mask) => write!(f, "invalid mask {:?}", mask),
+            Reason::InvalidAddress(address) => write!(f, "invalid address {:?}", address),
+            Reason::InvalidValue(value) => write!(f, "invalid value {:?}", value),
         }
     }
 }
 
+impl Error for ParseError {}
+
+// Reading a program from a `BufRead` can also fail on the reader itself
+#[derive(Debug)]
+pub enum ReadError {
+    Io(io::Error),
+    Parse(ParseError),
+}
+
+impl From<io::Error> for ReadError {
+    fn from(error: io::Error) -> Self {
+        ReadError::Io(error)
+    }
+}
+
+impl From<ParseError> for ReadError {
+    fn from(error: ParseError) -> Self {
+        ReadError::Parse(error)
+    }
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReadError::Io(error) => write!(f, "couldn't read program: {}", error),
+            ReadError::Parse(error) => write!(f, "couldn't parse program: {}", error),
+        }
+    }
+}
+
+impl Error for ReadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ReadError::Io(error) => Some(error),
+            ReadError::Parse(error) => Some(error),
+        }
+    }
+}
+
+fn parse_line<const N: usize>(index: usize, line: &str) -> Result<Instruction<N>, ParseError> {
+    line.parse().map_err(|reason| ParseError {
+        line: index + 1,
+        reason,
+    })
+}
+
+// Parses the instructions one line at a time, as they are read
+fn read_instructions<R: BufRead, const N: usize>(
+    reader: R,
+) -> impl Iterator<Item = Result<Instruction<N>, ReadError>> {
+    reader
+        .lines()
+        .enumerate()
+        .map(|(index, line)| Ok(parse_line(index, &line?)?))
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub struct InitializationProgram {
-    instructions: Vec<Instruction>,
-    mask: [MaskBit; REG_SIZE],
+pub struct InitializationProgram<const N: usize = REG_SIZE> {
+    instructions: Vec<Instruction<N>>,
+}
+
+impl<const N: usize> Default for InitializationProgram<N> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-impl InitializationProgram {
+impl<const N: usize> InitializationProgram<N> {
     pub fn new() -> Self {
+        let _: () = Reg::<N>::VALID_SIZE;
         Self {
             instructions: Vec::new(),
-            mask: [MaskBit::Leave; REG_SIZE],
         }
     }
 
-    pub fn run_v1(&mut self) -> Vec<Reg> {
-        let mut memory = Vec::<Reg>::new();
-        for instruction in self.instructions.iter().copied() {
+    pub fn len(&self) -> usize {
+        self.instructions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instructions.is_empty()
+    }
+
+    pub fn instructions(&self) -> &[Instruction<N>] {
+        &self.instructions
+    }
+
+    pub fn run_v1(&self) -> HashMap<u64, u64> {
+        let mut memory = HashMap::new();
+        self.run_v1_into(&mut memory);
+        memory
+    }
+
+    pub fn run_v1_into<M: Memory>(&self, memory: &mut M) {
+        self.run(&DecoderV1, memory);
+    }
+
+    pub fn run_v2(&self) -> HashMap<u64, u64> {
+        let mut memory = HashMap::new();
+        self.run_v2_into(&mut memory);
+        memory
+    }
+
+    pub fn run_v2_into<M: Memory>(&self, memory: &mut M) {
+        self.run(&DecoderV2, memory);
+    }
+
+    pub fn run<D: Decoder<N> + ?Sized, M: Memory>(&self, decoder: &D, memory: &mut M) {
+        Machine::<_, N>::with_memory(memory).run(self, decoder);
+    }
+
+    pub fn from_reader<R: BufRead>(reader: R) -> Result<Self, ReadError> {
+        let mut this = Self::new();
+        this.instructions = read_instructions(reader).collect::<Result<Vec<_>, _>>()?;
+        Ok(this)
+    }
+}
+
+impl<const N: usize> FromStr for InitializationProgram<N> {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut this = Self::new();
+        this.instructions = s
+            .lines()
+            .enumerate()
+            .map(|(index, line)| parse_line(index, line))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(this)
+    }
+}
+
+// Prints the program in the same format it is parsed from
+impl<const N: usize> fmt::Display for InitializationProgram<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for instruction in &self.instructions {
+            writeln!(f, "{}", instruction)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use symbolic::AddressCube;
+
+    const SMALL_EXAMPLE: &str = include_str!("../small");
+    const SMALL_EXAMPLE_V2: &str = include_str!("../small_v2");
+
+    #[test]
+    fn p1_small() {
+        let program: InitializationProgram =
+            SMALL_EXAMPLE.parse().expect("Couldn't parse small example");
+        let memory = program.run_v1();
+        assert_eq!(memory.sum(), 165);
+    }
+
+    #[test]
+    fn p2_small() {
+        let program: InitializationProgram = SMALL_EXAMPLE_V2
+            .parse()
+            .expect("Couldn't parse small example");
+        let memory = program.run_v2();
+        assert_eq!(memory.sum(), 208);
+    }
+
+    #[test]
+    fn other_widths() {
+        let program: InitializationProgram<4> = "mask = X1X0\nmem[3] = 9\n".parse().unwrap();
+        assert_eq!(program.run_v1().read(3), 0b1100);
+        assert_eq!(program.run_v2().len(), 4);
+
+        assert!("mask = 1X\n".parse::<InitializationProgram>().is_err());
+        assert_eq!(Reg::<64>::size_mask(), u64::MAX);
+        assert_eq!(Reg::<48>::new(u64::MAX >> 16).get(), (1 << 48) - 1);
+    }
+
+    fn check_widest_numbers<const N: usize>() {
+        let widest = Reg::<N>::size_mask();
+        let mask = "X".repeat(N);
+        let input = format!("mask = {}\nmem[{}] = {}\n", mask, widest, widest);
+        let program: InitializationProgram<N> = input.parse().unwrap();
+        assert_eq!(program.run_v1().read(widest), widest);
+        let input = format!("mask = {}\nmem[{}] = {}\n", "0".repeat(N), widest, widest);
+        let program: InitializationProgram<N> = input.parse().unwrap();
+        assert_eq!(program.run_v2().read(widest), widest);
+
+        if let Some(too_wide) = widest.checked_add(1) {
+            let input = format!("mask = {}\nmem[1] = {}\n", mask, too_wide);
+            assert_eq!(
+                input.parse::<InitializationProgram<N>>(),
+                Err(ParseError {
+                    line: 2,
+                    reason: Reason::InvalidValue(too_wide.to_string())
+                })
+            );
+            let input = format!("mask = {}\nmem[{}] = 1\n", mask, too_wide);
+            assert_eq!(
+                input.parse::<InitializationProgram<N>>(),
+                Err(ParseError {
+                    line: 2,
+                    reason: Reason::InvalidAddress(too_wide.to_string())
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn numbers_wider_than_registers() {
+        check_widest_numbers::<1>();
+        check_widest_numbers::<4>();
+        check_widest_numbers::<32>();
+        check_widest_numbers::<36>();
+        check_widest_numbers::<48>();
+        check_widest_numbers::<64>();
+
+        // The sum of two 64 bit values doesn't fit into 64 bits
+        let widest = Reg::<64>::size_mask();
+        let input = format!(
+            "mask = {}\nmem[1] = {}\nmem[2] = {}\n",
+            "X".repeat(64),
+            widest,
+            widest
+        );
+        let program: InitializationProgram<64> = input.parse().unwrap();
+        assert_eq!(program.run_v1().sum(), 2 * widest as u128);
+
+        let error = "mask = XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX\nmem[1] = 99999999999999\n"
+            .parse::<InitializationProgram>()
+            .unwrap_err();
+        assert_eq!(
+            error.reason,
+            Reason::InvalidValue("99999999999999".to_owned())
+        );
+        assert!("mem[1] = 68719476735"
+            .parse::<InitializationProgram<32>>()
+            .is_err());
+    }
+
+    #[test]
+    fn symbolic_matches_v2() {
+        for input in &[SMALL_EXAMPLE_V2, include_str!("../input")] {
+            let program: InitializationProgram = input.parse().unwrap();
+            let expected = program.run_v2();
+            let memory = program.run_v2_symbolic();
+
+            assert_eq!(memory.len(), expected.len() as u128);
+            assert_eq!(memory.sum(), expected.values().sum::<u64>() as u128);
+            for (&address, &value) in &expected {
+                assert_eq!(memory.get(address), Some(value));
+            }
+        }
+    }
+
+    #[test]
+    fn symbolic_many_floating_bits() {
+        let program: InitializationProgram = "\
+mask = 0000XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX
+mem[0] = 3
+mask = 0000XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX1
+mem[0] = 1
+"
+        .parse()
+        .unwrap();
+        let memory = program.run_v2_symbolic();
+        assert_eq!(memory.len(), 1 << 32);
+        assert_eq!(memory.sum(), 3 * (1 << 31) + (1 << 31));
+        assert_eq!(memory.get(6), Some(3));
+        assert_eq!(memory.get(7), Some(1));
+        assert_eq!(memory.get(1 << 33), None);
+    }
+
+    #[test]
+    fn sparse_v1() {
+        let program: InitializationProgram = "\
+mask = XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX
+mem[34359738368] = 5
+mem[0] = 7
+"
+        .parse()
+        .unwrap();
+        let memory = program.run_v1();
+        assert_eq!(memory.read(1 << 35), 5);
+        assert_eq!(memory.read(12), 0);
+        assert_eq!(memory.cells().count(), 2);
+        assert_eq!(memory.sum(), 12);
+    }
+
+    #[test]
+    fn custom_decoder() {
+        // A version 2 chip that also applies the mask to the value
+        struct MaskingV2;
+
+        impl<const N: usize> Decoder<N> for MaskingV2 {
+            fn decode_value(&self, mask: &Mask<N>, value: u64) -> u64 {
+                DecoderV1.decode_value(mask, value)
+            }
+
+            fn decode_address(&self, mask: &Mask<N>, address: u64) -> AddressCube {
+                DecoderV2.decode_address(mask, address)
+            }
+        }
+
+        let program: InitializationProgram = SMALL_EXAMPLE_V2.parse().unwrap();
+        let mut memory = HashMap::new();
+        program.run(&MaskingV2, &mut memory);
+        // 100 is masked to 50, the second write overwrites two of its four addresses
+        assert_eq!(memory.sum(), 2 * 50 + 8);
+
+        // Decoders can be picked at runtime, and every memory takes their cubes
+        let decoders: [&dyn Decoder<REG_SIZE>; 3] = [&DecoderV1, &DecoderV2, &MaskingV2];
+        for decoder in decoders.iter().copied() {
+            let mut expected = HashMap::new();
+            program.run(decoder, &mut expected);
+            let mut symbolic = symbolic::SymbolicMemory::default();
+            program.run(decoder, &mut symbolic);
+            assert!(memory::diff(&expected, &symbolic).is_empty());
+            assert_eq!(symbolic.sum(), expected.sum());
+        }
+    }
+
+    #[test]
+    fn machine_snapshots() {
+        let program: InitializationProgram = SMALL_EXAMPLE.parse().unwrap();
+        let mut machine: Machine = Machine::new();
+        assert!(machine.step(&program, &DecoderV1));
+        assert!(machine.step(&program, &DecoderV1));
+        assert_eq!(machine.memory().read(8), 73);
+
+        let snapshot = machine.clone();
+        machine.run(&program, &DecoderV1);
+        assert!(machine.is_finished(&program));
+        assert!(!machine.step(&program, &DecoderV1));
+        assert_eq!(machine.memory().sum(), 165);
+
+        // The snapshot is unaffected and picks up where it was taken
+        assert_eq!(snapshot.position(), 2);
+        assert_eq!(snapshot.memory().sum(), 73);
+        let mut resumed = snapshot.clone();
+        resumed.run(&program, &DecoderV1);
+        assert_eq!(resumed, machine);
+
+        // Running a program twice gives the same result
+        assert_eq!(program.run_v1(), program.run_v1());
+    }
+
+    #[test]
+    fn trace_and_diff() {
+        let program: InitializationProgram = SMALL_EXAMPLE_V2.parse().unwrap();
+        let mut events = Vec::new();
+        let mut machine: Machine = Machine::new();
+        machine.run_traced(&program, &DecoderV2, |event| events.push(event));
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].instruction, 1);
+        assert_eq!(events[0].writes.len(), 4);
+        assert_eq!(events[1].address, 26);
+        let overwritten = events[1]
+            .writes
+            .iter()
+            .find(|write| write.address == 26)
+            .unwrap();
+        assert_eq!(overwritten.before, Some(100));
+        assert_eq!(overwritten.after, 1);
+        assert!(events[1].to_string().contains("[26] 100 -> 1"));
+
+        let v1 = program.run_v1();
+        let v2 = machine.into_memory();
+        let differences = memory::diff(&v1, &v2);
+        assert_eq!(differences.len(), 10);
+        assert_eq!(
+            differences[0],
+            memory::Difference {
+                address: 16,
+                left: None,
+                right: Some(1)
+            }
+        );
+        assert!(memory::diff(&v2, &v2).is_empty());
+    }
+
+    #[test]
+    fn mask_algebra() {
+        let mask: Mask = "XXXXXXXXXXXXXXXXXXXXXXXXXXXXX1XXXX0X".parse().unwrap();
+        assert_eq!(mask.to_string(), "XXXXXXXXXXXXXXXXXXXXXXXXXXXXX1XXXX0X");
+        assert_eq!(mask.apply(11), 73);
+        assert!("XX".parse::<Mask>().is_err());
+        assert!("0000X".parse::<Mask<5>>().is_ok());
+        assert!("0000Y".parse::<Mask<5>>().is_err());
+
+        let first: Mask<4> = "1X0X".parse().unwrap();
+        let second: Mask<4> = "X01X".parse().unwrap();
+        let composed = first.compose(&second);
+        assert_eq!(composed.to_string(), "101X");
+        for value in 0..16 {
+            assert_eq!(composed.apply(value), second.apply(first.apply(value)));
+        }
+
+        assert!(first.overlaps(&"XX00".parse().unwrap()));
+        assert!(!first.overlaps(&second));
+        assert_eq!(first.floating_bits(), vec![0, 2]);
+        assert_eq!(first.address_count(), 4);
+    }
+
+    #[test]
+    fn mask_address_cube() {
+        let masks = [
+            "000000000000000000000000000000X1001X",
+            "00000000000000000000000000000000X0XX",
+        ];
+        for (mask, address) in masks.iter().zip(&[42, 26]) {
+            let mask: Mask = mask.parse().unwrap();
+            let addresses = mask.addresses(*address);
+            let cube = mask.address_cube(*address);
+
+            assert_eq!(addresses.len() as u128, mask.address_count());
+            assert_eq!(cube.size(), mask.address_count());
+            assert!(addresses.iter().all(|&a| cube.contains(a)));
+            let mut sorted = addresses.clone();
+            sorted.sort_unstable();
+            assert_eq!(cube.addresses().collect::<Vec<_>>(), sorted);
+        }
+    }
+
+    #[test]
+    fn parse_errors() {
+        let error = "mask = XX1\nme\n"
+            .parse::<InitializationProgram>()
+            .unwrap_err();
+        assert_eq!(error.line, 1);
+        assert_eq!(error.reason, Reason::InvalidMask("XX1".to_owned()));
+
+        let cases = [
+            ("me", Reason::MissingAssignment),
+            ("m = 3", Reason::UnknownTarget("m".to_owned())),
+            ("mem = 3", Reason::UnknownTarget("mem".to_owned())),
+            ("mem[x] = 3", Reason::InvalidAddress("x".to_owned())),
+            ("mem[3] = -1", Reason::InvalidValue("-1".to_owned())),
+        ];
+        for (line, reason) in cases.iter().cloned() {
+            let input = format!("mask = XXXX\nmem[1] = 2\n{}\n", line);
+            let error = input.parse::<InitializationProgram<4>>().unwrap_err();
+            assert_eq!(error, ParseError { line: 3, reason });
+        }
+        assert_eq!(
+            "mask = 1X\n"
+                .parse::<InitializationProgram<4>>()
+                .unwrap_err()
+                .to_string(),
+            "line 1: invalid mask \"1X\""
+        );
+    }
+
+    #[test]
+    fn streaming() {
+        let mut machine: Machine = Machine::new();
+        machine
+            .run_reader(SMALL_EXAMPLE_V2.as_bytes(), &DecoderV2)
+            .unwrap();
+        assert_eq!(machine.memory().sum(), 208);
+        assert_eq!(machine.position(), 4);
+
+        let program = InitializationProgram::from_reader(SMALL_EXAMPLE.as_bytes()).unwrap();
+        assert_eq!(
+            program,
+            SMALL_EXAMPLE.parse::<InitializationProgram>().unwrap()
+        );
+
+        // Everything before the broken line has already been executed
+        let mut machine: Machine = Machine::new();
+        let input =
+            "mask = XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX\nmem[7] = 101\nmem[8] = x\nmem[9] = 1\n";
+        match machine.run_reader(input.as_bytes(), &DecoderV1) {
+            Err(ReadError::Parse(error)) => assert_eq!(error.line, 3),
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert_eq!(machine.memory().read(7), 101);
+        assert_eq!(Memory::get(machine.memory(), 9), None);
+    }
+
+    // Reference models that work on the textual masks one bit at a time
+    fn reference_v1<const N: usize>(program: &InitializationProgram<N>) -> HashMap<u64, u64> {
+        let mut memory = HashMap::new();
+        let mut mask = "X".repeat(N);
+        for instruction in &program.instructions {
             match instruction {
-                Instruction::SetMask(val) => self.mask = val,
+                Instruction::SetMask(new) => mask = new.to_string(),
                 Instruction::SetMemory { address, value } => {
-                    let value = self.apply_mask_to(value);
-                    if address >= memory.len() {
-                        memory.resize(address + 1, Default::default());
+                    let mut result = 0;
+                    for (i, c) in mask.chars().enumerate() {
+                        let bit = (value >> (N - i - 1)) & 1;
+                        result = (result << 1)
+                            | match c {
+                                '1' => 1,
+                                '0' => 0,
+                                _ => bit,
+                            };
                     }
-                    memory[address].set(value);
+                    memory.insert(*address as u64, result);
                 }
             }
         }
         memory
     }
 
-    pub fn run_v2(&mut self) -> HashMap<u64, u64> {
+    fn reference_v2<const N: usize>(program: &InitializationProgram<N>) -> HashMap<u64, u64> {
         let mut memory = HashMap::new();
-
-        for instruction in self.instructions.iter().copied() {
+        let mut mask = "0".repeat(N);
+        for instruction in &program.instructions {
             match instruction {
-                Instruction::SetMask(val) => self.mask = val,
+                Instruction::SetMask(new) => mask = new.to_string(),
                 Instruction::SetMemory { address, value } => {
-                    let addresses = self.addresses_from_mask(address);
-
-                    for actual_address in &addresses {
-                        let _ = memory.insert(*actual_address, value);
+                    let floating = mask.chars().filter(|&c| c == 'X').count();
+                    for choice in 0..1u64 << floating {
+                        let mut choices = (0..floating).rev().map(|j| (choice >> j) & 1);
+                        let mut result = 0;
+                        for (i, c) in mask.chars().enumerate() {
+                            let bit = (*address as u64 >> (N - i - 1)) & 1;
+                            result = (result << 1)
+                                | match c {
+                                    '1' => 1,
+                                    '0' => bit,
+                                    _ => choices.next().unwrap(),
+                                };
+                        }
+                        memory.insert(result, *value);
                     }
                 }
             }
         }
-
         memory
     }
 
-    fn addresses_from_mask(&self, original_address: usize) -> Vec<u64> {
-        let mut addresses = vec![original_address as u64 | self.bits_of(MaskBit::Set)];
-        for i in 0..REG_SIZE {
-            // Leave now means "Floating"
-            if self.mask[i] == MaskBit::Leave {
-                let mut new_addresses = Vec::new();
-                for a in addresses {
-                    let bit = 1 << (REG_SIZE - i - 1);
-                    new_addresses.push(a & !bit);
-                    new_addresses.push(a | bit);
-                }
+    fn check_against_references<const N: usize>(shape: generate::ProgramShape) {
+        for seed in 0..20 {
+            let program = InitializationProgram::<N>::random(seed, shape);
+            assert_eq!(program.len(), shape.masks * (1 + shape.writes_per_mask));
+
+            let expected = reference_v1(&program);
+            assert_eq!(program.run_v1(), expected, "v1, seed {}", seed);
 
-                addresses = new_addresses;
+            let expected = reference_v2(&program);
+            assert_eq!(program.run_v2(), expected, "v2, seed {}", seed);
+            let symbolic = program.run_v2_symbolic();
+            assert_eq!(symbolic.len(), expected.len() as u128, "seed {}", seed);
+            assert_eq!(
+                symbolic.sum(),
+                expected.values().map(|&v| v as u128).sum::<u128>(),
+                "seed {}",
+                seed
+            );
+            for (&address, &value) in &expected {
+                assert_eq!(symbolic.get(address), Some(value), "seed {}", seed);
             }
         }
+    }
 
-        addresses
+    #[test]
+    fn differential() {
+        use generate::ProgramShape;
+
+        check_against_references::<36>(ProgramShape::default());
+        check_against_references::<36>(ProgramShape {
+            masks: 5,
+            writes_per_mask: 10,
+            floating_bits: 0,
+            address_bits: 36,
+        });
+        check_against_references::<8>(ProgramShape {
+            masks: 20,
+            writes_per_mask: 3,
+            floating_bits: 5,
+            address_bits: 8,
+        });
+        check_against_references::<64>(ProgramShape {
+            masks: 4,
+            writes_per_mask: 4,
+            floating_bits: 9,
+            address_bits: 64,
+        });
+        check_against_references::<1>(ProgramShape {
+            masks: 3,
+            writes_per_mask: 2,
+            floating_bits: 1,
+            address_bits: 1,
+        });
     }
 
-    fn bits_of(&self, target: MaskBit) -> u64 {
-        self.mask
-            .iter()
-            .fold(0, |acc, &cur| (acc << 1) | ((cur == target) as u64))
+    #[test]
+    fn random_programs() {
+        let shape = generate::ProgramShape::default();
+        let program: InitializationProgram = InitializationProgram::random(7, shape);
+        assert_eq!(program, InitializationProgram::random(7, shape));
+        assert_ne!(program, InitializationProgram::random(8, shape));
+
+        for instruction in &program.instructions {
+            match instruction {
+                Instruction::SetMask(mask) => {
+                    assert_eq!(mask.floating_bits().len(), shape.floating_bits)
+                }
+                Instruction::SetMemory { address, value } => {
+                    assert!(*address < 1 << shape.address_bits);
+                    assert_eq!(value & !Reg::<REG_SIZE>::size_mask(), 0);
+                }
+            }
+        }
     }
 
-    fn apply_mask_to(&self, value: u64) -> u64 {
-        let set_mask = self.bits_of(MaskBit::Set);
-        let unset_mask = self.bits_of(MaskBit::Unset);
+    #[test]
+    fn display_round_trip() {
+        for input in &[SMALL_EXAMPLE, SMALL_EXAMPLE_V2, include_str!("../input")] {
+            let program: InitializationProgram = input.parse().unwrap();
+            assert_eq!(program.to_string(), *input);
+            // Every instruction prints as its own line
+            let lines: Vec<String> = program
+                .instructions()
+                .iter()
+                .map(|i| i.to_string())
+                .collect();
+            assert_eq!(lines, input.lines().collect::<Vec<_>>());
+            assert_eq!(
+                lines[0].parse::<Instruction>(),
+                Ok(program.instructions()[0])
+            );
+        }
 
-        (value | set_mask) & !unset_mask
+        let program: InitializationProgram<12> =
+            InitializationProgram::random(3, generate::ProgramShape::default());
+        assert_eq!(program.to_string().parse(), Ok(program));
     }
-}
 
-impl FromStr for InitializationProgram {
-    type Err = ();
+    #[test]
+    fn transformations() {
+        let program: InitializationProgram<4> = "\
+mask = 1XX0
+mem[1] = 5
+mask = 0000
+mask = XX00
+mem[2] = 6
+mask = 0000
+mem[1] = 7
+mask = 1111
+mask = XX0X
+mem[2] = 8
+"
+        .parse()
+        .unwrap();
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut this = Self::new();
-        this.instructions = s
-            .lines()
-            .map(Instruction::from_str)
-            .collect::<Result<Vec<_>, _>>()?;
-        Ok(this)
+        // Both decoders overwrite everything the write of 6 touches, but version 2
+        // doesn't overwrite address 9 of the write of 5
+        let normalized = program.normalized();
+        assert_eq!(
+            normalized.to_string(),
+            "mask = 1XX0\nmem[1] = 5\nmask = 0000\nmem[1] = 7\nmask = XX0X\nmem[2] = 8\n"
+        );
+        assert_eq!(normalized.run_v1(), program.run_v1());
+        assert_eq!(normalized.run_v2(), program.run_v2());
+        assert_eq!(normalized.normalized(), normalized);
+
+        let shape = generate::ProgramShape {
+            masks: 30,
+            writes_per_mask: 4,
+            floating_bits: 2,
+            address_bits: 4,
+        };
+        for seed in 0..20 {
+            let program = InitializationProgram::<8>::random(seed, shape);
+            let normalized = program.normalized();
+            assert!(normalized.len() < program.len());
+            assert_eq!(normalized.run_v1(), program.run_v1(), "seed {}", seed);
+            assert_eq!(normalized.run_v2(), program.run_v2(), "seed {}", seed);
+        }
     }
 }
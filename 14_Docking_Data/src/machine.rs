@@ -0,0 +1,178 @@
+use crate::decoder::Decoder;
+use crate::memory::Memory;
+use crate::trace::{CellWrite, WriteEvent};
+use crate::{read_instructions, InitializationProgram, Instruction, Mask, ReadError, REG_SIZE};
+use std::collections::HashMap;
+use std::io::BufRead;
+
+// Everything that changes while an `InitializationProgram` runs. Cloning a machine
+// takes a snapshot that can be resumed later on, independently of the original.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Machine<M = HashMap<u64, u64>, const N: usize = REG_SIZE> {
+    mask: Mask<N>,
+    memory: M,
+    // Index of the next instruction to execute
+    position: usize,
+}
+
+impl<M: Memory + Default, const N: usize> Default for Machine<M, N> {
+    fn default() -> Self {
+        Self::with_memory(M::default())
+    }
+}
+
+impl<M: Memory + Default, const N: usize> Machine<M, N> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<M: Memory, const N: usize> Machine<M, N> {
+    pub fn with_memory(memory: M) -> Self {
+        Self {
+            mask: Mask::default(),
+            memory,
+            position: 0,
+        }
+    }
+
+    pub fn mask(&self) -> &Mask<N> {
+        &self.mask
+    }
+
+    pub fn memory(&self) -> &M {
+        &self.memory
+    }
+
+    pub fn into_memory(self) -> M {
+        self.memory
+    }
+
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn is_finished(&self, program: &InitializationProgram<N>) -> bool {
+        self.position >= program.instructions.len()
+    }
+
+    // Executes the next instruction of `program`, returns false if there is none left
+    pub fn step<D: Decoder<N> + ?Sized>(
+        &mut self,
+        program: &InitializationProgram<N>,
+        decoder: &D,
+    ) -> bool {
+        self.step_inner(program, decoder, None)
+    }
+
+    // Like `step`, but hands every memory write to `log`
+    pub fn step_traced<D, F>(
+        &mut self,
+        program: &InitializationProgram<N>,
+        decoder: &D,
+        mut log: F,
+    ) -> bool
+    where
+        D: Decoder<N> + ?Sized,
+        F: FnMut(WriteEvent<N>),
+    {
+        self.step_inner(program, decoder, Some(&mut log))
+    }
+
+    // Runs the rest of `program`
+    pub fn run<D: Decoder<N> + ?Sized>(&mut self, program: &InitializationProgram<N>, decoder: &D) {
+        while self.step(program, decoder) {}
+    }
+
+    pub fn run_traced<D, F>(&mut self, program: &InitializationProgram<N>, decoder: &D, mut log: F)
+    where
+        D: Decoder<N> + ?Sized,
+        F: FnMut(WriteEvent<N>),
+    {
+        while self.step_inner(program, decoder, Some(&mut log)) {}
+    }
+
+    // Reads the instructions from `reader` and runs each one as soon as it was parsed,
+    // the program never has to fit into memory as a whole
+    pub fn run_reader<R: BufRead, D: Decoder<N> + ?Sized>(
+        &mut self,
+        reader: R,
+        decoder: &D,
+    ) -> Result<(), ReadError> {
+        for instruction in read_instructions(reader) {
+            self.execute(instruction?, decoder, None);
+        }
+        Ok(())
+    }
+
+    pub fn run_reader_traced<R, D, F>(
+        &mut self,
+        reader: R,
+        decoder: &D,
+        mut log: F,
+    ) -> Result<(), ReadError>
+    where
+        R: BufRead,
+        D: Decoder<N> + ?Sized,
+        F: FnMut(WriteEvent<N>),
+    {
+        for instruction in read_instructions(reader) {
+            self.execute(instruction?, decoder, Some(&mut log));
+        }
+        Ok(())
+    }
+
+    fn step_inner<D: Decoder<N> + ?Sized>(
+        &mut self,
+        program: &InitializationProgram<N>,
+        decoder: &D,
+        log: Option<&mut dyn FnMut(WriteEvent<N>)>,
+    ) -> bool {
+        match program.instructions.get(self.position) {
+            Some(&instruction) => {
+                self.execute(instruction, decoder, log);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn execute<D: Decoder<N> + ?Sized>(
+        &mut self,
+        instruction: Instruction<N>,
+        decoder: &D,
+        log: Option<&mut dyn FnMut(WriteEvent<N>)>,
+    ) {
+        match instruction {
+            Instruction::SetMask(val) => self.mask = val,
+            Instruction::SetMemory { address, value } => {
+                let decoded_value = decoder.decode_value(&self.mask, value);
+                let cube = decoder.decode_address(&self.mask, address as u64);
+
+                // Only a log needs every single address
+                if let Some(log) = log {
+                    let writes = cube
+                        .addresses()
+                        .map(|actual_address| CellWrite {
+                            address: actual_address,
+                            before: self.memory.get(actual_address),
+                            after: decoded_value,
+                        })
+                        .collect();
+                    self.memory.write_cube(cube, decoded_value);
+                    log(WriteEvent {
+                        instruction: self.position,
+                        mask: self.mask,
+                        address: address as u64,
+                        value,
+                        writes,
+                    });
+                } else {
+                    self.memory.write_cube(cube, decoded_value);
+                }
+            }
+        }
+
+        self.position += 1;
+    }
+}
@@ -1,18 +1,25 @@
-use docking_data::{InitializationProgram, Reg};
-use std::str::FromStr;
+use docking_data::decoder::{DecoderV1, DecoderV2};
+use docking_data::machine::Machine;
+use docking_data::memory::Memory;
+use std::env;
+use std::fs::File;
+use std::io::BufReader;
 
 fn main() {
-    let mut program =
-        InitializationProgram::from_str(include_str!("../input")).expect("Couldn't parse input");
+    let filename = env::args().nth(1).unwrap_or_else(|| "input".to_owned());
+    let open = || BufReader::new(File::open(&filename).expect("Couldn't open file"));
 
     // Part one
-    let memory = program.run_v1();
-    println!(
-        "Part 1 - Sum of memory: {}",
-        memory.iter().map(Reg::get).sum::<u64>()
-    );
+    let mut machine: Machine = Machine::new();
+    if let Err(error) = machine.run_reader(open(), &DecoderV1) {
+        panic!("{}", error);
+    }
+    println!("Part 1 - Sum of memory: {}", machine.memory().sum());
 
     // Part two
-    let memory = program.run_v2();
-    println!("Part 2 - Sum of memory: {}", memory.values().sum::<u64>());
+    let mut machine: Machine = Machine::new();
+    if let Err(error) = machine.run_reader(open(), &DecoderV2) {
+        panic!("{}", error);
+    }
+    println!("Part 2 - Sum of memory: {}", machine.memory().sum());
 }
@@ -0,0 +1,122 @@
+use crate::symbolic::AddressCube;
+use crate::{MaskBit, Reg, REG_SIZE};
+use std::convert::{TryFrom, TryInto};
+use std::fmt;
+use std::str::FromStr;
+
+// The first bit is the most significant one, just like in the `mask = ...` lines
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Mask<const N: usize = REG_SIZE>([MaskBit; N]);
+
+impl<const N: usize> Default for Mask<N> {
+    fn default() -> Self {
+        Self([MaskBit::Leave; N])
+    }
+}
+
+impl<const N: usize> From<[MaskBit; N]> for Mask<N> {
+    fn from(bits: [MaskBit; N]) -> Self {
+        Self(bits)
+    }
+}
+
+impl<const N: usize> Mask<N> {
+    pub fn bits(&self) -> &[MaskBit; N] {
+        &self.0
+    }
+
+    // Every bit of the register that is `target` in the mask
+    pub fn bits_of(&self, target: MaskBit) -> u64 {
+        self.0
+            .iter()
+            .fold(0, |acc, &cur| (acc << 1) | ((cur == target) as u64))
+    }
+
+    // Overwrites the bits of `value` that are `Set` or `Unset` in the mask
+    pub fn apply(&self, value: u64) -> u64 {
+        let set_mask = self.bits_of(MaskBit::Set);
+        let unset_mask = self.bits_of(MaskBit::Unset);
+
+        (value | set_mask) & !unset_mask
+    }
+
+    // A mask that does the same to a value as applying `self` and then `later`
+    pub fn compose(&self, later: &Self) -> Self {
+        let mut bits = self.0;
+        for (bit, &later_bit) in bits.iter_mut().zip(later.0.iter()) {
+            if later_bit != MaskBit::Leave {
+                *bit = later_bit;
+            }
+        }
+        Self(bits)
+    }
+
+    // Whether some register value matches both masks when reading `X` as "any bit"
+    pub fn overlaps(&self, other: &Self) -> bool {
+        self.0
+            .iter()
+            .zip(other.0.iter())
+            .all(|(&a, &b)| a == MaskBit::Leave || b == MaskBit::Leave || a == b)
+    }
+
+    // Positions of the floating bits, counted from the least significant bit
+    pub fn floating_bits(&self) -> Vec<usize> {
+        let floating = self.bits_of(MaskBit::Leave);
+        (0..N).filter(|i| floating & (1 << i) != 0).collect()
+    }
+
+    // Number of addresses a version 2 write under this mask goes to
+    pub fn address_count(&self) -> u128 {
+        1 << self.bits_of(MaskBit::Leave).count_ones()
+    }
+
+    // The addresses a version 2 write under this mask goes to, without enumerating them
+    pub fn address_cube(&self, address: u64) -> AddressCube {
+        AddressCube::new(
+            self.bits_of(MaskBit::Leave),
+            address | self.bits_of(MaskBit::Set),
+        )
+    }
+
+    pub fn addresses(&self, original_address: u64) -> Vec<u64> {
+        let mut addresses = vec![original_address | self.bits_of(MaskBit::Set)];
+        for (i, &mask_bit) in self.0.iter().enumerate() {
+            // Leave now means "Floating"
+            if mask_bit == MaskBit::Leave {
+                let mut new_addresses = Vec::new();
+                for a in addresses {
+                    let bit = 1 << (N - i - 1);
+                    new_addresses.push(a & !bit);
+                    new_addresses.push(a | bit);
+                }
+
+                addresses = new_addresses;
+            }
+        }
+
+        addresses
+    }
+}
+
+impl<const N: usize> FromStr for Mask<N> {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let _: () = Reg::<N>::VALID_SIZE;
+        let bits = s
+            .chars()
+            .map(MaskBit::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+        let bits: [MaskBit; N] = bits.as_slice().try_into().map_err(|_| ())?;
+        Ok(Self(bits))
+    }
+}
+
+impl<const N: usize> fmt::Display for Mask<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for &bit in &self.0 {
+            write!(f, "{}", char::from(bit))?;
+        }
+        Ok(())
+    }
+}
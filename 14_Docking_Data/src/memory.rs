@@ -0,0 +1,91 @@
+use crate::symbolic::AddressCube;
+use std::collections::{BTreeSet, HashMap};
+
+// Memory of the docking computer, addresses that were never written to read as zero
+pub trait Memory {
+    fn get(&self, address: u64) -> Option<u64>;
+
+    fn set(&mut self, address: u64, value: u64);
+
+    // Stores `value` at every address of `cube`, one at a time unless the memory
+    // knows better
+    fn write_cube(&mut self, cube: AddressCube, value: u64) {
+        for address in cube.addresses() {
+            self.set(address, value);
+        }
+    }
+
+    // Every address that was written to, together with its current value
+    fn cells(&self) -> Box<dyn Iterator<Item = (u64, u64)> + '_>;
+
+    fn read(&self, address: u64) -> u64 {
+        self.get(address).unwrap_or(0)
+    }
+
+    // Wide enough for every value of a 64 bit register at every address
+    fn sum(&self) -> u128 {
+        self.cells().map(|(_, value)| value as u128).sum()
+    }
+}
+
+impl Memory for HashMap<u64, u64> {
+    fn get(&self, address: u64) -> Option<u64> {
+        HashMap::get(self, &address).copied()
+    }
+
+    fn set(&mut self, address: u64, value: u64) {
+        let _ = self.insert(address, value);
+    }
+
+    fn cells(&self) -> Box<dyn Iterator<Item = (u64, u64)> + '_> {
+        Box::new(self.iter().map(|(&address, &value)| (address, value)))
+    }
+}
+
+impl<M: Memory + ?Sized> Memory for &mut M {
+    fn get(&self, address: u64) -> Option<u64> {
+        (**self).get(address)
+    }
+
+    fn set(&mut self, address: u64, value: u64) {
+        (**self).set(address, value)
+    }
+
+    fn write_cube(&mut self, cube: AddressCube, value: u64) {
+        (**self).write_cube(cube, value)
+    }
+
+    fn cells(&self) -> Box<dyn Iterator<Item = (u64, u64)> + '_> {
+        (**self).cells()
+    }
+
+    fn sum(&self) -> u128 {
+        (**self).sum()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Difference {
+    pub address: u64,
+    pub left: Option<u64>,
+    pub right: Option<u64>,
+}
+
+// Every address where the two memories disagree, ordered by address
+pub fn diff<A: Memory, B: Memory>(left: &A, right: &B) -> Vec<Difference> {
+    let addresses: BTreeSet<u64> = left
+        .cells()
+        .chain(right.cells())
+        .map(|(address, _)| address)
+        .collect();
+
+    addresses
+        .into_iter()
+        .map(|address| Difference {
+            address,
+            left: left.get(address),
+            right: right.get(address),
+        })
+        .filter(|difference| difference.left != difference.right)
+        .collect()
+}
@@ -0,0 +1,154 @@
+use crate::decoder::DecoderV2;
+use crate::memory::Memory;
+use crate::InitializationProgram;
+
+// All addresses that agree with `fixed` on every bit that is not `floating`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AddressCube {
+    floating: u64,
+    fixed: u64,
+}
+
+impl AddressCube {
+    pub fn new(floating: u64, fixed: u64) -> Self {
+        Self {
+            floating,
+            fixed: fixed & !floating,
+        }
+    }
+
+    pub fn floating(&self) -> u64 {
+        self.floating
+    }
+
+    pub fn fixed(&self) -> u64 {
+        self.fixed
+    }
+
+    // Number of addresses in the cube
+    pub fn size(&self) -> u128 {
+        1 << self.floating.count_ones()
+    }
+
+    // Every address in the cube, in ascending order
+    pub fn addresses(&self) -> impl Iterator<Item = u64> {
+        let (floating, fixed) = (self.floating, self.fixed);
+        // Counts through the subsets of the floating bits
+        let mut next = Some(0u64);
+        std::iter::from_fn(move || {
+            let subset = next?;
+            next = Some(subset.wrapping_sub(floating) & floating).filter(|&s| s != 0);
+            Some(fixed | subset)
+        })
+    }
+
+    pub fn contains(&self, address: u64) -> bool {
+        (address ^ self.fixed) & !self.floating == 0
+    }
+
+    pub fn intersects(&self, other: &Self) -> bool {
+        (self.fixed ^ other.fixed) & !self.floating & !other.floating == 0
+    }
+
+    // Splits the addresses of `self` that are not in `other` into disjoint cubes
+    pub fn subtract(&self, other: &Self) -> Vec<Self> {
+        if !self.intersects(other) {
+            return vec![*self];
+        }
+
+        let mut pieces = Vec::new();
+        let mut rest = *self;
+        let mut split_bits = self.floating & !other.floating;
+        while split_bits != 0 {
+            let bit = split_bits & split_bits.wrapping_neg();
+            split_bits &= !bit;
+
+            // The half that disagrees with `other` on this bit is outside of it,
+            // the other half still has to be checked against the remaining bits
+            rest.floating &= !bit;
+            pieces.push(Self {
+                floating: rest.floating,
+                fixed: rest.fixed | (!other.fixed & bit),
+            });
+            rest.fixed |= other.fixed & bit;
+        }
+
+        pieces
+    }
+}
+
+// Memory of the version 2 decoder that stores every write as disjoint address cubes
+// instead of single addresses, so floating bits don't blow up the memory usage
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SymbolicMemory {
+    writes: Vec<(AddressCube, u64)>,
+}
+
+impl SymbolicMemory {
+    pub fn write(&mut self, cube: AddressCube, value: u64) {
+        let mut writes = Vec::with_capacity(self.writes.len() + 1);
+        for (old_cube, old_value) in self.writes.drain(..) {
+            writes.extend(
+                old_cube
+                    .subtract(&cube)
+                    .into_iter()
+                    .map(|piece| (piece, old_value)),
+            );
+        }
+        writes.push((cube, value));
+        self.writes = writes;
+    }
+
+    // Number of addresses that were written to
+    pub fn len(&self) -> u128 {
+        self.writes.iter().map(|(cube, _)| cube.size()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.writes.is_empty()
+    }
+
+    pub fn cubes(&self) -> impl Iterator<Item = (AddressCube, u64)> + '_ {
+        self.writes.iter().copied()
+    }
+}
+
+impl Memory for SymbolicMemory {
+    fn get(&self, address: u64) -> Option<u64> {
+        self.writes
+            .iter()
+            .find(|(cube, _)| cube.contains(address))
+            .map(|&(_, value)| value)
+    }
+
+    fn set(&mut self, address: u64, value: u64) {
+        self.write(AddressCube::new(0, address), value);
+    }
+
+    fn write_cube(&mut self, cube: AddressCube, value: u64) {
+        self.write(cube, value);
+    }
+
+    fn cells(&self) -> Box<dyn Iterator<Item = (u64, u64)> + '_> {
+        Box::new(
+            self.writes
+                .iter()
+                .flat_map(|&(cube, value)| cube.addresses().map(move |address| (address, value))),
+        )
+    }
+
+    fn sum(&self) -> u128 {
+        self.writes
+            .iter()
+            .map(|(cube, value)| cube.size() * *value as u128)
+            .sum()
+    }
+}
+
+impl<const N: usize> InitializationProgram<N> {
+    pub fn run_v2_symbolic(&self) -> SymbolicMemory {
+        let mut memory = SymbolicMemory::default();
+        self.run(&DecoderV2, &mut memory);
+        memory
+    }
+}
@@ -0,0 +1,39 @@
+use crate::Mask;
+use std::fmt;
+
+// A single address written by a `mem[address] = value` instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CellWrite {
+    pub address: u64,
+    pub before: Option<u64>,
+    pub after: u64,
+}
+
+// Everything a traced machine logs for one `mem[address] = value` instruction
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct WriteEvent<const N: usize> {
+    pub instruction: usize,
+    pub mask: Mask<N>,
+    pub address: u64,
+    pub value: u64,
+    pub writes: Vec<CellWrite>,
+}
+
+impl<const N: usize> fmt::Display for WriteEvent<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "#{} mask = {} mem[{}] = {}",
+            self.instruction, self.mask, self.address, self.value
+        )?;
+        for write in &self.writes {
+            match write.before {
+                Some(before) => {
+                    write!(f, "\n    [{}] {} -> {}", write.address, before, write.after)?
+                }
+                None => write!(f, "\n    [{}] unset -> {}", write.address, write.after)?,
+            }
+        }
+        Ok(())
+    }
+}
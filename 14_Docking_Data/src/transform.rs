@@ -0,0 +1,75 @@
+use crate::symbolic::AddressCube;
+use crate::{InitializationProgram, Instruction, Mask};
+use std::collections::HashSet;
+
+// Rewrites that leave the memory after running the program unchanged,
+// no matter if it runs with the version 1 or the version 2 decoder
+impl<const N: usize> InitializationProgram<N> {
+    // Removes the writes of which every address is overwritten later on
+    pub fn without_dead_writes(&self) -> Self {
+        let mut masks = Vec::with_capacity(self.instructions.len());
+        let mut mask = Mask::<N>::default();
+        for &instruction in &self.instructions {
+            if let Instruction::SetMask(new) = instruction {
+                mask = new;
+            }
+            masks.push(mask);
+        }
+
+        // Walk backwards and remember everything that is written later on
+        let mut later_v1 = HashSet::new();
+        let mut later_v2: Vec<AddressCube> = Vec::new();
+        let mut keep = vec![true; self.instructions.len()];
+        for (i, &instruction) in self.instructions.iter().enumerate().rev() {
+            if let Instruction::SetMemory { address, .. } = instruction {
+                let address = address as u64;
+                let cube = masks[i].address_cube(address);
+                let v1_dead = later_v1.contains(&address);
+                let v2_dead = later_v2
+                    .iter()
+                    .fold(vec![cube], |rest, later| {
+                        rest.iter()
+                            .flat_map(|piece| piece.subtract(later))
+                            .collect()
+                    })
+                    .is_empty();
+
+                if v1_dead && v2_dead {
+                    keep[i] = false;
+                } else {
+                    later_v1.insert(address);
+                    later_v2.push(cube);
+                }
+            }
+        }
+
+        let mut this = Self::new();
+        this.instructions = self
+            .instructions
+            .iter()
+            .zip(keep)
+            .filter(|&(_, keep)| keep)
+            .map(|(&instruction, _)| instruction)
+            .collect();
+        this
+    }
+
+    // Of several mask lines in a row, only the last one has any effect
+    pub fn with_merged_masks(&self) -> Self {
+        let mut this = Self::new();
+        for &instruction in &self.instructions {
+            if let (Instruction::SetMask(_), Some(Instruction::SetMask(_))) =
+                (instruction, this.instructions.last())
+            {
+                this.instructions.pop();
+            }
+            this.instructions.push(instruction);
+        }
+        this
+    }
+
+    // Removing writes can leave mask lines next to each other, so merge afterwards
+    pub fn normalized(&self) -> Self {
+        self.without_dead_writes().with_merged_masks()
+    }
+}
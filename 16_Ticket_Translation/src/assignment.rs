@@ -0,0 +1,246 @@
+use crate::{Rule, TicketTranslator};
+
+// Outcome of assigning a rule to every column of the tickets. An assignment lists the
+// rule of each column, in column order, just like `TicketTranslator::translate`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Assignment<'r> {
+    Unique(Vec<&'r Rule>),
+    Multiple {
+        // At most as many as were asked for, `truncated` tells if there are more
+        assignments: Vec<Vec<&'r Rule>>,
+        truncated: bool,
+        // The fields that are not in the same column in all of the assignments
+        ambiguous: Vec<&'r Rule>,
+    },
+    // The fields compete for fewer columns than there are of them, or the other way around
+    Unsatisfiable {
+        fields: Vec<&'r Rule>,
+        columns: Vec<usize>,
+    },
+}
+
+impl<'r> Assignment<'r> {
+    pub fn unique(self) -> Option<Vec<&'r Rule>> {
+        match self {
+            Assignment::Unique(rules) => Some(rules),
+            _ => None,
+        }
+    }
+}
+
+// `candidates[rule]` holds every column that the rule could describe
+#[derive(Debug, Clone)]
+struct Graph {
+    candidates: Vec<Vec<usize>>,
+    columns: usize,
+}
+
+// Rule of each column and column of each rule
+#[derive(Debug, Clone)]
+struct Matching {
+    column_of: Vec<Option<usize>>,
+    rule_of: Vec<Option<usize>>,
+}
+
+impl Graph {
+    // Maximum matching with augmenting paths (Kuhn's algorithm)
+    fn max_matching(&self) -> Matching {
+        let mut matching = Matching {
+            column_of: vec![None; self.candidates.len()],
+            rule_of: vec![None; self.columns],
+        };
+        for rule in 0..self.candidates.len() {
+            self.augment(rule, &mut matching, &mut vec![false; self.columns]);
+        }
+        matching
+    }
+
+    fn augment(&self, rule: usize, matching: &mut Matching, visited: &mut [bool]) -> bool {
+        for &column in &self.candidates[rule] {
+            if visited[column] {
+                continue;
+            }
+            visited[column] = true;
+            let free = match matching.rule_of[column] {
+                Some(other) => self.augment(other, matching, visited),
+                None => true,
+            };
+            if free {
+                matching.column_of[rule] = Some(column);
+                matching.rule_of[column] = Some(rule);
+                return true;
+            }
+        }
+        false
+    }
+
+    // Another perfect matching that doesn't pair `rule` with its current column, if any
+    fn without_edge(&self, rule: usize, matching: &Matching) -> Option<Matching> {
+        let column = matching.column_of[rule]?;
+        let mut graph = self.clone();
+        graph.candidates[rule].retain(|&c| c != column);
+        let mut other = matching.clone();
+        other.column_of[rule] = None;
+        other.rule_of[column] = None;
+        if graph.augment(rule, &mut other, &mut vec![false; self.columns]) {
+            Some(other)
+        } else {
+            None
+        }
+    }
+
+    // Every perfect matching, as long as there are no more than `limit` of them. Each call
+    // either finds the matching unique or splits the rest into two disjoint halves, one
+    // that keeps an edge of `matching` and one that avoids it, so no branch is a dead end.
+    fn enumerate(&self, matching: Matching, limit: usize, found: &mut Vec<Matching>) {
+        if found.len() >= limit {
+            return;
+        }
+
+        let split = (0..self.candidates.len())
+            .find_map(|rule| Some((rule, self.without_edge(rule, &matching)?)));
+        match split {
+            None => found.push(matching),
+            Some((rule, other)) => {
+                let column = matching.column_of[rule].unwrap();
+                let mut keeping = self.clone();
+                for (r, candidates) in keeping.candidates.iter_mut().enumerate() {
+                    if r == rule {
+                        *candidates = vec![column];
+                    } else {
+                        candidates.retain(|&c| c != column);
+                    }
+                }
+                keeping.enumerate(matching, limit, found);
+
+                let mut avoiding = self.clone();
+                avoiding.candidates[rule].retain(|&c| c != column);
+                avoiding.enumerate(other, limit, found);
+            }
+        }
+    }
+
+    // Alternating paths from the unmatched vertices on one side reach a set of vertices
+    // that has fewer neighbours than members, which is why no perfect matching exists
+    fn hall_violator(&self, matching: &Matching) -> (Vec<usize>, Vec<usize>) {
+        let mut rules = vec![false; self.candidates.len()];
+        let mut columns = vec![false; self.columns];
+
+        if matching.column_of.iter().any(Option::is_none) {
+            let mut stack: Vec<usize> = (0..self.candidates.len())
+                .filter(|&rule| matching.column_of[rule].is_none())
+                .collect();
+            while let Some(rule) = stack.pop() {
+                if std::mem::replace(&mut rules[rule], true) {
+                    continue;
+                }
+                for &column in &self.candidates[rule] {
+                    columns[column] = true;
+                    if let Some(next) = matching.rule_of[column] {
+                        stack.push(next);
+                    }
+                }
+            }
+        } else {
+            let mut stack: Vec<usize> = (0..self.columns)
+                .filter(|&column| matching.rule_of[column].is_none())
+                .collect();
+            while let Some(column) = stack.pop() {
+                if std::mem::replace(&mut columns[column], true) {
+                    continue;
+                }
+                for (rule, candidates) in self.candidates.iter().enumerate() {
+                    if candidates.contains(&column) {
+                        rules[rule] = true;
+                        if let Some(next) = matching.column_of[rule] {
+                            stack.push(next);
+                        }
+                    }
+                }
+            }
+        }
+
+        let selected = |set: Vec<bool>| {
+            set.into_iter()
+                .enumerate()
+                .filter(|&(_, selected)| selected)
+                .map(|(i, _)| i)
+                .collect()
+        };
+        (selected(rules), selected(columns))
+    }
+}
+
+impl TicketTranslator {
+    fn candidate_graph(&self) -> Graph {
+        let valid_tickets: Vec<&[usize]> = self
+            .other_tickets
+            .iter()
+            .filter(|ticket| self.ticket_is_valid(ticket))
+            .map(Vec::as_slice)
+            .collect();
+
+        let columns = self.own_ticket.len();
+        let candidates = self
+            .rules
+            .iter()
+            .map(|rule| {
+                (0..columns)
+                    .filter(|&column| {
+                        valid_tickets
+                            .iter()
+                            .all(|ticket| rule.check(ticket[column]))
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Graph {
+            candidates,
+            columns,
+        }
+    }
+
+    // Lists no more than `limit` assignments if there are several
+    pub fn assign(&self, limit: usize) -> Assignment<'_> {
+        let graph = self.candidate_graph();
+        let matching = graph.max_matching();
+        let perfect =
+            self.rules.len() == graph.columns && matching.column_of.iter().all(Option::is_some);
+
+        if !perfect {
+            let (fields, columns) = graph.hall_violator(&matching);
+            return Assignment::Unsatisfiable {
+                fields: fields.into_iter().map(|i| &self.rules[i]).collect(),
+                columns,
+            };
+        }
+
+        let to_rules = |matching: &Matching| -> Vec<&Rule> {
+            matching
+                .rule_of
+                .iter()
+                .map(|rule| &self.rules[rule.unwrap()])
+                .collect()
+        };
+        let ambiguous: Vec<&Rule> = (0..self.rules.len())
+            .filter(|&rule| graph.without_edge(rule, &matching).is_some())
+            .map(|rule| &self.rules[rule])
+            .collect();
+        if ambiguous.is_empty() {
+            return Assignment::Unique(to_rules(&matching));
+        }
+
+        // Look for one more than asked for, to know if the list is complete
+        let mut found = Vec::new();
+        graph.enumerate(matching, limit.saturating_add(1), &mut found);
+        let truncated = found.len() > limit;
+        found.truncate(limit);
+
+        Assignment::Multiple {
+            assignments: found.iter().map(to_rules).collect(),
+            truncated,
+            ambiguous,
+        }
+    }
+}
@@ -1,14 +1,92 @@
+use std::env;
 use std::error::Error;
-use ticket_translation::TicketTranslator;
+use std::fs::File;
+use std::io::BufWriter;
+use ticket_translation::{Aggregate, Assignment, Fields, QueryResult, TicketTranslator, Tickets};
+
+enum Export {
+    JsonLines,
+    Csv,
+}
 
 fn main() -> Result<(), Box<dyn Error>> {
-    let translator = TicketTranslator::new("input")?;
+    let mut args = env::args().skip(1);
+    let filename = args.next().unwrap_or_else(|| "input".to_owned());
+    // Optionally export the decoded tickets as `--json <file>` or `--csv <file>`
+    let export = match args.next().as_deref() {
+        Some("--json") => Some(Export::JsonLines),
+        Some("--csv") => Some(Export::Csv),
+        Some(other) => return Err(format!("Unknown argument: {}", other).into()),
+        None => None,
+    };
+    let export = match export {
+        Some(format) => Some((format, args.next().ok_or("Missing export file.")?)),
+        None => None,
+    };
+    let translator = TicketTranslator::new(&filename)?;
     println!("Ticket scanning error rate: {}", translator.error_rate());
 
-    println!(
-        "Departure number product: {}",
-        translator.departure_numbers().iter().product::<usize>()
-    );
+    let assignment = translator.assign(10);
+    let unique = matches!(assignment, Assignment::Unique(_));
+    match assignment {
+        Assignment::Unique(rule_of_column) => {
+            let product = translator.query(
+                Fields::Pattern("departure*"),
+                Tickets::Own,
+                Aggregate::Product,
+            );
+            if let Some(QueryResult::Number(Some(product))) = product {
+                println!("Departure number product: {}", product);
+            }
+
+            if let Some((format, path)) = &export {
+                let decoded = translator.decode_with(&rule_of_column);
+                let mut out = BufWriter::new(File::create(path)?);
+                match format {
+                    Export::JsonLines => decoded.write_json_lines(&mut out)?,
+                    Export::Csv => decoded.write_csv(&mut out)?,
+                }
+            }
+        }
+        Assignment::Multiple {
+            assignments,
+            truncated,
+            ambiguous,
+        } => {
+            let ambiguous: Vec<&str> = ambiguous.iter().map(|rule| rule.name()).collect();
+            println!(
+                "{}{} possible assignments, ambiguous fields: {}",
+                if truncated { "More than " } else { "" },
+                assignments.len(),
+                ambiguous.join(", ")
+            );
+        }
+        Assignment::Unsatisfiable { fields, columns } => {
+            let fields: Vec<&str> = fields.iter().map(|rule| rule.name()).collect();
+            println!(
+                "No assignment possible, fields {} compete for columns {:?}",
+                fields.join(", "),
+                columns
+            );
+        }
+    }
+
+    // Some mis-scanned tickets might be all that stands in the way
+    if let Some(tolerant) = translator.assign_tolerant().filter(|_| !unique) {
+        println!("Best supported assignment:");
+        for field in tolerant.fields {
+            println!(
+                "    column {}: {} ({:.1}% of tickets agree)",
+                field.column,
+                field.rule.name(),
+                100.0 * field.confidence
+            );
+        }
+    }
+
+    if export.is_some() && !unique {
+        return Err("Couldn't export, the tickets don't determine every field.".into());
+    }
 
     Ok(())
 }
@@ -0,0 +1,72 @@
+use crate::Rule;
+use std::ops::RangeInclusive;
+
+// Splits the numbers into segments on which every rule either accepts all numbers or none.
+// The segment of a number and with it the rules accepting it are found by binary search.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct IntervalIndex {
+    // First number of each segment, sorted, the first segment starts at zero
+    starts: Vec<usize>,
+    // Indices of the rules that accept the numbers of each segment
+    accepting: Vec<Vec<usize>>,
+    // The numbers that some rule accepts, merged into sorted, disjoint ranges
+    valid: Vec<RangeInclusive<usize>>,
+}
+
+impl IntervalIndex {
+    pub fn new(rules: &[Rule]) -> Self {
+        let mut starts: Vec<usize> = rules
+            .iter()
+            .flat_map(|rule| rule.ranges().iter().chain(rule.exclusions()))
+            .flat_map(|range| std::iter::once(*range.start()).chain(range.end().checked_add(1)))
+            .chain(std::iter::once(0))
+            .collect();
+        starts.sort_unstable();
+        starts.dedup();
+
+        // Within a segment nothing changes, so its first number stands in for all of them
+        let accepting: Vec<Vec<usize>> = starts
+            .iter()
+            .map(|&start| {
+                (0..rules.len())
+                    .filter(|&rule| rules[rule].check(start))
+                    .collect()
+            })
+            .collect();
+
+        let mut valid: Vec<RangeInclusive<usize>> = Vec::new();
+        for (i, rules) in accepting.iter().enumerate() {
+            if rules.is_empty() {
+                continue;
+            }
+            let end = starts.get(i + 1).map_or(usize::MAX, |next| next - 1);
+            match valid.last_mut() {
+                Some(last) if last.end().checked_add(1) == Some(starts[i]) => {
+                    *last = *last.start()..=end
+                }
+                _ => valid.push(starts[i]..=end),
+            }
+        }
+
+        Self {
+            starts,
+            accepting,
+            valid,
+        }
+    }
+
+    pub fn accepts(&self, num: usize) -> bool {
+        let after = self.valid.partition_point(|range| *range.start() <= num);
+        after > 0 && self.valid[after - 1].contains(&num)
+    }
+
+    // Indices of the rules that accept `num`, in the order of the rules
+    pub fn rules_accepting(&self, num: usize) -> &[usize] {
+        let segment = self.starts.partition_point(|&start| start <= num) - 1;
+        &self.accepting[segment]
+    }
+
+    pub fn valid_ranges(&self) -> &[RangeInclusive<usize>] {
+        &self.valid
+    }
+}
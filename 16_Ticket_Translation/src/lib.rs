@@ -1,46 +1,116 @@
-use std::collections::HashSet;
 use std::convert::TryFrom;
 use std::error::Error;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
 use std::ops::RangeInclusive;
 use std::str::FromStr;
 
+pub mod assignment;
+pub mod index;
+pub mod parse;
+pub mod query;
+pub mod records;
+pub mod tolerant;
+pub mod validation;
+
+pub use assignment::Assignment;
+pub use index::IntervalIndex;
+pub use parse::ParseError;
+pub use query::{Aggregate, Fields, QueryResult, Tickets};
+pub use tolerant::{FieldAssignment, TolerantAssignment};
+
+// A number matches a rule if it lies in any of the ranges and in none of the exclusions.
+// A rule without ranges accepts every number that is not excluded.
 #[derive(Clone, Debug, Hash, PartialEq, Eq)]
 pub struct Rule {
     name: String,
-    ranges: [RangeInclusive<usize>; 2],
+    ranges: Vec<RangeInclusive<usize>>,
+    exclusions: Vec<RangeInclusive<usize>>,
 }
 
 impl Rule {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn ranges(&self) -> &[RangeInclusive<usize>] {
+        &self.ranges
+    }
+
+    pub fn exclusions(&self) -> &[RangeInclusive<usize>] {
+        &self.exclusions
+    }
+
     pub fn check(&self, num: usize) -> bool {
-        self.ranges.iter().any(|range| range.contains(&num))
+        (self.ranges.is_empty() || self.ranges.iter().any(|range| range.contains(&num)))
+            && !self.exclusions.iter().any(|range| range.contains(&num))
     }
 }
 
+// Parses one `or`-separated term of a rule: `a-b`, `a`, `>= a`, `> a`, `<= a`, `< a`,
+// or any of these behind `not`
+fn parse_term(term: &str) -> Result<(bool, RangeInclusive<usize>), Box<dyn Error>> {
+    let (excluded, term) = match term.strip_prefix("not ") {
+        Some(rest) => (true, rest.trim_start()),
+        None => (false, term),
+    };
+
+    let range = if let Some(start) = term.strip_prefix(">=") {
+        start.trim().parse::<usize>()?..=usize::MAX
+    } else if let Some(start) = term.strip_prefix('>') {
+        let start = start.trim().parse::<usize>()?;
+        start
+            .checked_add(1)
+            .ok_or("nothing is greater than the maximum")?..=usize::MAX
+    } else if let Some(end) = term.strip_prefix("<=") {
+        0..=end.trim().parse::<usize>()?
+    } else if let Some(end) = term.strip_prefix('<') {
+        let end = end.trim().parse::<usize>()?;
+        0..=end.checked_sub(1).ok_or("nothing is less than zero")?
+    } else if let Some((start, end)) = term.split_once('-') {
+        let start = start.trim().parse::<usize>()?;
+        let end = end.trim().parse::<usize>()?;
+        if start > end {
+            return Err(format!("empty range {}", term).into());
+        }
+        start..=end
+    } else {
+        let value = term.trim().parse::<usize>()?;
+        value..=value
+    };
+
+    Ok((excluded, range))
+}
+
 impl TryFrom<String> for Rule {
     type Error = Box<dyn Error>;
 
     fn try_from(line: String) -> Result<Self, Self::Error> {
         let split_index = line.find(": ").ok_or("split index not found")?;
-        let both_rules = &line[split_index + 2..];
 
-        let mut iter = both_rules.split(" or ");
-        let first = iter.next().ok_or("first rule not found")?;
-        let second = iter.next().ok_or("second rule not found")?;
-
-        let parse_rule = |s: &str| -> Result<_, Box<dyn Error>> {
-            let mut iter = s.split("-");
-            let start = iter.next().ok_or("start not found")?.parse::<usize>()?;
-            let end = iter.next().ok_or("end not found")?.parse::<usize>()?;
-            Ok(start..=end)
-        };
+        let mut ranges = Vec::new();
+        let mut exclusions = Vec::new();
+        for term in line[split_index + 2..].split(" or ") {
+            match parse_term(term.trim())? {
+                (false, range) => ranges.push(range),
+                (true, range) => exclusions.push(range),
+            }
+        }
 
-        let ranges = [parse_rule(first)?, parse_rule(second)?];
         let mut name = line;
         name.truncate(split_index);
 
-        Ok(Self { name, ranges })
+        Ok(Self {
+            name,
+            ranges,
+            exclusions,
+        })
+    }
+}
+
+impl FromStr for Rule {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::try_from(s.to_owned())
     }
 }
 
@@ -49,136 +119,612 @@ pub struct TicketTranslator {
     rules: Vec<Rule>,
     own_ticket: Vec<usize>,
     other_tickets: Vec<Vec<usize>>,
+    // 1-based line of the input that each nearby ticket was read from
+    nearby_lines: Vec<usize>,
+    index: IntervalIndex,
 }
 
 impl TicketTranslator {
-    pub fn new(filename: &str) -> Result<Self, Box<dyn Error>> {
-        let mut lines = BufReader::new(File::open(filename)?).lines();
-        eprintln!("Opened file");
-
-        let mut rules = Vec::new();
-        while let Some(Ok(line)) = lines.next() {
-            if line == "your ticket:" {
-                break;
-            } else if !line.is_empty() {
-                rules.push(Rule::try_from(line)?);
-            }
-        }
-
-        eprintln!("{} rules read", rules.len());
-
-        let own_ticket = lines
-            .next()
-            .ok_or("own ticket not found")??
-            .split(",")
-            .map(usize::from_str)
-            .collect::<Result<Vec<_>, _>>()?;
-
-        eprintln!("Own ticket scanned");
-
-        // Skip empty and header line
-        let _ = lines.next();
-        let _ = lines.next();
-
-        let mut other_tickets = Vec::new();
-        while let Some(Ok(line)) = lines.next() {
-            let ticket = line
-                .split(",")
-                .map(usize::from_str)
-                .collect::<Result<Vec<_>, _>>()?;
-            other_tickets.push(ticket);
-        }
-
-        eprintln!("{} other tickets scanned", other_tickets.len());
-
-        Ok(Self {
+    fn from_parts(
+        rules: Vec<Rule>,
+        own_ticket: Vec<usize>,
+        other_tickets: Vec<Vec<usize>>,
+        nearby_lines: Vec<usize>,
+    ) -> Self {
+        let index = IntervalIndex::new(&rules);
+        Self {
             rules,
             own_ticket,
             other_tickets,
-        })
+            nearby_lines,
+            index,
+        }
     }
 
+    // Sum of every value of the nearby tickets that matches no rule at all
     pub fn error_rate(&self) -> usize {
         self.other_tickets
             .iter()
-            .map(|ticket| self.find_invalid_num(&ticket))
             .flatten()
+            .copied()
+            .filter(|&num| !self.accepts(num))
             .sum()
     }
 
-    fn find_invalid_num(&self, ticket: &[usize]) -> Option<usize> {
-        ticket
+    fn accepts(&self, num: usize) -> bool {
+        self.index.accepts(num)
+    }
+
+    // Also works for tickets that were never read into the translator
+    pub fn ticket_is_valid(&self, ticket: &[usize]) -> bool {
+        ticket.iter().all(|&num| self.accepts(num))
+    }
+
+    pub fn rules_accepting(&self, num: usize) -> Vec<&Rule> {
+        self.index
+            .rules_accepting(num)
             .iter()
-            .copied()
-            .find(|num| !self.rules.iter().any(|rule| rule.check(*num)))
+            .map(|&rule| &self.rules[rule])
+            .collect()
+    }
+
+    pub fn index(&self) -> &IntervalIndex {
+        &self.index
+    }
+
+    // The rule of each column, if the tickets determine it unambiguously
+    pub fn translate(&self) -> Option<Vec<&Rule>> {
+        self.assign(1).unique()
+    }
+
+    pub fn departure_numbers(&self) -> Option<Vec<usize>> {
+        Some(
+            self.decode()?
+                .select(Fields::Pattern("departure*"), Tickets::Own),
+        )
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
 
-    fn ticket_is_valid(&self, ticket: &[usize]) -> bool {
-        self.find_invalid_num(ticket).is_none()
+    #[test]
+    fn p1_small() {
+        let translator = TicketTranslator::new("small").unwrap();
+        assert_eq!(translator.error_rate(), 71);
     }
 
-    pub fn translate<'s>(&'s self) -> Vec<&'s Rule> {
-        let valid_tickets: Vec<&[usize]> = self
-            .other_tickets
+    #[test]
+    fn p2_small() {
+        let translator = TicketTranslator::new("small_p2").unwrap();
+        let names: Vec<&str> = translator
+            .translate()
+            .unwrap()
             .iter()
-            .filter(|ticket| self.ticket_is_valid(&ticket))
-            .map(Vec::as_slice)
+            .map(|r| r.name())
             .collect();
+        assert_eq!(names, ["row", "class", "seat"]);
+    }
 
-        let mut possible_columns: Vec<HashSet<usize>> = vec![HashSet::new(); self.rules.len()];
+    #[test]
+    fn rule_grammar() {
+        let rule: Rule = "class: 1-3 or 5-7".parse().unwrap();
+        assert_eq!(rule.name(), "class");
+        assert_eq!(rule.ranges(), &[1..=3, 5..=7]);
+        assert!(rule.check(7) && !rule.check(4));
 
-        for (i, rule) in self.rules.iter().enumerate() {
-            for cur_index in 0..self.own_ticket.len() {
-                let all_tickets_match = valid_tickets
-                    .iter()
-                    .all(|ticket| rule.check(ticket[cur_index]));
-                if all_tickets_match {
-                    possible_columns[i].insert(cur_index);
-                }
+        let rule: Rule = "zone: 1-3 or 5 or 9-10 or >= 40 or not 42".parse().unwrap();
+        assert_eq!(rule.ranges().len(), 4);
+        assert_eq!(rule.exclusions(), &[42..=42]);
+        for &(num, expected) in &[(5, true), (6, false), (10, true), (39, false), (41, true)] {
+            assert_eq!(rule.check(num), expected, "{}", num);
+        }
+        assert!(!rule.check(42));
+        assert!(rule.check(usize::MAX));
+
+        let rule: Rule = "gate: not 12 or not 20-30".parse().unwrap();
+        assert!(rule.check(0) && rule.check(13) && rule.check(31));
+        assert!(!rule.check(12) && !rule.check(25));
+
+        let rule: Rule = "track: > 3 or < 1 or <= 2".parse().unwrap();
+        assert_eq!(rule.ranges(), &[4..=usize::MAX, 0..=0, 0..=2]);
+        assert!(!rule.check(3));
+
+        for bad in &[
+            "class 1-3",
+            "class: 3-1",
+            "class: a-b",
+            "class: < 0",
+            "class: 1 or",
+        ] {
+            assert!(bad.parse::<Rule>().is_err(), "{}", bad);
+        }
+    }
+
+    // The first ticket is our own and also the first nearby one
+    fn from_parts(rules: &[&str], tickets: &[&[usize]]) -> TicketTranslator {
+        let line = |ticket: &[usize]| -> String {
+            let values: Vec<String> = ticket.iter().map(usize::to_string).collect();
+            values.join(",")
+        };
+        let nearby: Vec<String> = tickets.iter().map(|ticket| line(ticket)).collect();
+        format!(
+            "{}\n\nyour ticket:\n{}\n\nnearby tickets:\n{}\n",
+            rules.join("\n"),
+            line(tickets[0]),
+            nearby.join("\n")
+        )
+        .parse()
+        .unwrap()
+    }
+
+    // Linear congruential generator, good enough for test data
+    fn random(state: &mut u64) -> usize {
+        *state = state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (*state >> 33) as usize
+    }
+
+    fn names(rules: &[&Rule]) -> Vec<String> {
+        rules.iter().map(|rule| rule.name().to_owned()).collect()
+    }
+
+    #[test]
+    fn ambiguous_assignment() {
+        // a and b both fit the first two columns, c only fits the last one
+        let translator = from_parts(
+            &["a: 1-10", "b: 1-10", "c: 20-30 or 5", "d: >= 0"],
+            &[&[1, 2, 20, 40], &[3, 4, 25, 50]],
+        );
+        assert_eq!(translator.translate(), None);
+        assert_eq!(translator.departure_numbers(), None);
+
+        match translator.assign(10) {
+            Assignment::Multiple {
+                assignments,
+                truncated,
+                ambiguous,
+            } => {
+                let mut assignments: Vec<_> = assignments.iter().map(|a| names(a)).collect();
+                assignments.sort();
+                assert_eq!(assignments, [["a", "b", "c", "d"], ["b", "a", "c", "d"]]);
+                assert!(!truncated);
+                assert_eq!(names(&ambiguous), ["a", "b"]);
             }
+            other => panic!("unexpected assignment {:?}", other),
+        }
+
+        match translator.assign(1) {
+            Assignment::Multiple {
+                assignments,
+                truncated,
+                ..
+            } => assert_eq!((assignments.len(), truncated), (1, true)),
+            other => panic!("unexpected assignment {:?}", other),
         }
+    }
 
-        let mut progress = true;
-        let mut found_column: Vec<Option<usize>> = vec![None; self.rules.len()];
-        while progress {
-            progress = false;
-            for i in 0..possible_columns.len() {
-                if possible_columns[i].len() == 1 {
-                    progress = true;
-                    let field = possible_columns[i].drain().next().unwrap();
-                    found_column[i] = Some(field);
-                    for set in possible_columns.iter_mut() {
-                        set.remove(&field);
-                    }
-                }
+    #[test]
+    fn many_assignments() {
+        // Every rule fits every column, so there are 6! assignments
+        let rules = [
+            "a: >= 0", "b: >= 0", "c: >= 0", "d: >= 0", "e: >= 0", "f: >= 0",
+        ];
+        let translator = from_parts(&rules, &[&[1, 2, 3, 4, 5, 6]]);
+        match translator.assign(1000) {
+            Assignment::Multiple {
+                assignments,
+                truncated,
+                ambiguous,
+            } => {
+                let distinct: HashSet<Vec<String>> = assignments.iter().map(|a| names(a)).collect();
+                assert_eq!(distinct.len(), 720);
+                assert_eq!(assignments.len(), 720);
+                assert!(!truncated);
+                assert_eq!(ambiguous.len(), 6);
             }
+            other => panic!("unexpected assignment {:?}", other),
         }
+    }
+
+    #[test]
+    fn unsatisfiable_assignment() {
+        // a, b and c all only fit the first two columns
+        let translator = from_parts(
+            &["a: 1-10", "b: 1-10", "c: 1-10", "d: >= 0"],
+            &[&[1, 2, 20, 40], &[3, 4, 25, 50]],
+        );
+        assert_eq!(translator.translate(), None);
+        assert_eq!(
+            translator.assign(10),
+            Assignment::Unsatisfiable {
+                fields: translator.rules[0..3].iter().collect(),
+                columns: vec![0, 1],
+            }
+        );
 
-        let mut rule_of_column = vec![None; self.own_ticket.len()];
+        // More columns than rules
+        let translator = from_parts(&["a: 1-10", "b: >= 0"], &[&[1, 20, 30]]);
+        match translator.assign(10) {
+            Assignment::Unsatisfiable { fields, columns } => {
+                assert_eq!(names(&fields), ["b"]);
+                assert_eq!(columns, [1, 2]);
+            }
+            other => panic!("unexpected assignment {:?}", other),
+        }
+    }
 
-        found_column
-            .into_iter()
-            .map(Option::unwrap)
-            .enumerate()
-            .for_each(|(rule_index, column_index)| {
-                rule_of_column[column_index] = Some(rule_index);
-            });
+    #[test]
+    fn validation_report() {
+        let translator = TicketTranslator::new("small").unwrap();
+        let report = translator.validate();
+        let invalid: Vec<(usize, usize, usize)> = report
+            .tickets
+            .iter()
+            .flat_map(|t| t.invalid.iter().map(move |v| (t.ticket, v.column, v.value)))
+            .collect();
+        assert_eq!(invalid, [(1, 1, 4), (2, 0, 55), (3, 2, 12)]);
 
-        rule_of_column
-            .into_iter()
-            .map(Option::unwrap)
-            .map(|i| &self.rules[i])
-            .collect()
+        // 4 is right between class 1-3 and class 5-7
+        let nearest = &report.tickets[0].invalid[0].nearest;
+        assert_eq!(nearest.len(), 2);
+        assert!(nearest
+            .iter()
+            .all(|near| matches!(near.miss, validation::Miss::Outside { distance: 1, .. })));
+        assert_eq!(
+            report.tickets[1].invalid[0].nearest[0].miss,
+            validation::Miss::Outside {
+                range: 45..=50,
+                distance: 5
+            }
+        );
+
+        let mut csv = Vec::new();
+        report.write_csv(&mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "ticket,line,column,value,nearest\n\
+             1,10,1,4,\"class 1-3; class 5-7\"\n\
+             2,11,0,55,\"seat 45-50\"\n\
+             3,12,2,12,\"row 6-11; seat 13-40\"\n"
+        );
+        assert!(report
+            .to_string()
+            .contains("column 0: 55 matches no rule, nearest seat 45-50 (off by 5)"));
+
+        // Every invalid value counts, zero and second ones in a ticket as well
+        let translator = from_parts(&["a: 5-10"], &[&[5, 6], &[0, 5], &[11, 12]]);
+        assert_eq!(translator.error_rate(), 23);
+        assert_eq!(translator.validate().invalid_values(), 3);
+        assert!(!translator.validate().is_valid());
+
+        // Values that are only rejected by an exclusion name the exclusion
+        let translator = from_parts(
+            &["a: 1-20 or not 12", "b: not 10-14", "c: 30-40"],
+            &[&[1], &[12], &[25]],
+        );
+        let report = translator.validate();
+        let misses: Vec<&validation::Miss> = report.tickets[0].invalid[0]
+            .nearest
+            .iter()
+            .map(|near| &near.miss)
+            .collect();
+        assert_eq!(
+            misses,
+            [
+                &validation::Miss::Excluded(12..=12),
+                &validation::Miss::Excluded(10..=14)
+            ]
+        );
+        assert_eq!(
+            report.to_string(),
+            "ticket 1 (line 10):\n    column 0: 12 matches no rule, nearest a not 12 (excluded), b not 10-14 (excluded)\n"
+        );
+        // b accepts 1 and 25, so ticket 1 is the only invalid one
+        assert_eq!(report.tickets.len(), 1);
+
+        let translator = from_parts(&["a: 1-20 or not 12", "c: 30-40"], &[&[1], &[25]]);
+        let report = translator.validate();
+        let mut csv = Vec::new();
+        report.write_csv(&mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "ticket,line,column,value,nearest\n1,9,0,25,\"a 1-20; c 30-40\"\n"
+        );
+        assert!(report.tickets.iter().flat_map(|t| &t.invalid).all(|v| v
+            .nearest
+            .iter()
+            .all(|near| !matches!(near.miss, validation::Miss::Outside { distance: 0, .. }))));
+    }
+
+    #[test]
+    fn parsing() {
+        let from_file = TicketTranslator::new("small").unwrap();
+        let from_str: TicketTranslator = include_str!("../small").parse().unwrap();
+        let from_reader =
+            TicketTranslator::from_reader(include_str!("../small").as_bytes()).unwrap();
+        for translator in &[from_str, from_reader] {
+            assert_eq!(translator.rules, from_file.rules);
+            assert_eq!(translator.own_ticket, from_file.own_ticket);
+            assert_eq!(translator.other_tickets, from_file.other_tickets);
+        }
+        assert_eq!(from_file.own_ticket, [7, 1, 14]);
+        assert_eq!(from_file.other_tickets.len(), 4);
+
+        let error = |input: &str| input.parse::<TicketTranslator>().unwrap_err().to_string();
+        assert_eq!(
+            error("a: 1-3\nb 4\n"),
+            "invalid rule in line 2: split index not found"
+        );
+        assert_eq!(
+            error("a: 1-3\n\nnearby tickets:\n1\n"),
+            "expected \"your ticket:\" in line 3"
+        );
+        assert_eq!(error("a: 1-3\n"), "expected \"your ticket:\" in line 2");
+        assert_eq!(
+            error("a: 1-3\nyour ticket:\n\nnearby tickets:\n"),
+            "expected our own ticket in line 4"
+        );
+        assert_eq!(
+            error("a: 1-3\nyour ticket:\n1,2\n3,4\n"),
+            "expected \"nearby tickets:\" in line 4"
+        );
+        assert_eq!(
+            error("a: 1-3\nyour ticket:\n1,x\n"),
+            "invalid number \"x\" in line 3"
+        );
+        assert_eq!(
+            error("a: 1-3\nyour ticket:\n1,2\nnearby tickets:\n1,2\n1,2,3\n"),
+            "ticket in line 6 has 3 values, but our own ticket has 2"
+        );
+        assert!(matches!(
+            TicketTranslator::new("does not exist"),
+            Err(ParseError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn decoded_records() {
+        let translator = TicketTranslator::new("small_p2").unwrap();
+        let decoded = translator.decode().unwrap();
+        assert_eq!(decoded.own_ticket.get("class"), Some(12));
+        assert_eq!(decoded.own_ticket.get("train"), None);
+        assert_eq!(decoded.records().count(), 4);
+
+        let mut json = Vec::new();
+        decoded.write_json_lines(&mut json).unwrap();
+        let json = String::from_utf8(json).unwrap();
+        assert_eq!(
+            json.lines().next(),
+            Some(r#"{"row":11,"class":12,"seat":13}"#)
+        );
+        assert_eq!(json.lines().count(), 4);
+
+        let mut csv = Vec::new();
+        decoded.write_csv(&mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "row,class,seat\n11,12,13\n3,9,18\n15,1,5\n5,14,9\n"
+        );
+
+        // Invalid nearby tickets are left out, names are escaped
+        let translator = from_parts(&["a \"b\", c: 1-10", "d: 20-30"], &[&[1, 20], &[40, 25]]);
+        let rules: Vec<&Rule> = translator.rules.iter().collect();
+        let decoded = translator.decode_with(&rules);
+        assert_eq!(decoded.nearby_tickets.len(), 1);
+        let mut json = Vec::new();
+        decoded.write_json_lines(&mut json).unwrap();
+        assert_eq!(
+            String::from_utf8(json).unwrap(),
+            "{\"a \\\"b\\\", c\":1,\"d\":20}\n{\"a \\\"b\\\", c\":1,\"d\":20}\n"
+        );
+        let mut csv = Vec::new();
+        decoded.write_csv(&mut csv).unwrap();
+        assert!(String::from_utf8(csv)
+            .unwrap()
+            .starts_with("\"a \"\"b\"\", c\",d\n"));
+    }
+
+    #[test]
+    fn field_queries() {
+        let translator = TicketTranslator::new("small_p2").unwrap();
+        let query =
+            |fields, tickets, aggregate| translator.query(fields, tickets, aggregate).unwrap();
+
+        assert_eq!(
+            query(Fields::Pattern("seat"), Tickets::Own, Aggregate::List),
+            QueryResult::List(vec![13])
+        );
+        assert_eq!(
+            query(Fields::Pattern("*"), Tickets::Own, Aggregate::Product),
+            QueryResult::Number(Some(11 * 12 * 13))
+        );
+        assert_eq!(
+            query(Fields::Pattern("*s*"), Tickets::AllValid, Aggregate::Sum),
+            QueryResult::Number(Some(12 + 13 + 9 + 18 + 1 + 5 + 14 + 9))
+        );
+        assert_eq!(
+            query(Fields::Pattern("row"), Tickets::AllValid, Aggregate::Max),
+            QueryResult::Number(Some(15))
+        );
+        assert_eq!(
+            query(Fields::Pattern("r*w"), Tickets::AllValid, Aggregate::Min),
+            QueryResult::Number(Some(3))
+        );
+        assert_eq!(
+            query(
+                Fields::Pattern("arrival*"),
+                Tickets::AllValid,
+                Aggregate::Min
+            ),
+            QueryResult::Number(None)
+        );
+        let short = |name: &str| name.len() <= 4;
+        assert_eq!(
+            query(Fields::Predicate(&short), Tickets::Own, Aggregate::List),
+            QueryResult::List(vec![11, 13])
+        );
+        assert_eq!(translator.departure_numbers(), Some(vec![]));
+
+        for &(pattern, name, expected) in &[
+            ("departure*", "departure time", true),
+            ("departure*", "arrival time", false),
+            ("*time", "departure time", true),
+            ("d*e*e", "departure time", true),
+            ("d*x*e", "departure time", false),
+            ("seat", "seats", false),
+            ("a*a", "a", false),
+            ("**", "", true),
+        ] {
+            assert_eq!(
+                Fields::Pattern(pattern).matches(name),
+                expected,
+                "{} {}",
+                pattern,
+                name
+            );
+        }
+        assert_eq!(
+            Aggregate::Product.apply(vec![usize::MAX, 2]),
+            QueryResult::Number(None)
+        );
     }
 
-    pub fn departure_numbers(&self) -> Vec<usize> {
-        let rule_of_column = self.translate();
+    #[test]
+    fn interval_index() {
+        let translator = TicketTranslator::new("small").unwrap();
+        assert_eq!(translator.index().valid_ranges(), &[1..=3, 5..=11, 13..=50]);
+        let names = |num| -> Vec<&str> {
+            translator
+                .rules_accepting(num)
+                .iter()
+                .map(|rule| rule.name())
+                .collect()
+        };
+        assert_eq!(names(7), ["class", "row"]);
+        assert_eq!(names(40), ["row", "seat"]);
+        assert_eq!(names(12), Vec::<&str>::new());
+        assert_eq!(names(usize::MAX), Vec::<&str>::new());
+
+        let rules: Vec<Rule> = [
+            "a: 3-9 or 20 or not 5",
+            "b: >= 100 or not 150-160",
+            "c: not 7",
+            "d: 0-2 or 10-19",
+            "e: 8-30 or not 12-13",
+        ]
+        .iter()
+        .map(|rule| rule.parse().unwrap())
+        .collect();
+        let index = IntervalIndex::new(&rules);
+        for num in (0..200).chain(usize::MAX - 5..=usize::MAX) {
+            let expected: Vec<usize> = (0..rules.len()).filter(|&i| rules[i].check(num)).collect();
+            assert_eq!(index.rules_accepting(num), expected.as_slice(), "{}", num);
+            assert_eq!(index.accepts(num), !expected.is_empty(), "{}", num);
+        }
 
-        self.own_ticket
+        // Many tickets at once
+        let translator = TicketTranslator::new("input").unwrap();
+        let mut state = 42u64;
+        let tickets: Vec<Vec<usize>> = (0..100_000)
+            .map(|_| (0..20).map(|_| random(&mut state) % 1000).collect())
+            .collect();
+        let valid = tickets
             .iter()
-            .enumerate()
-            .filter(|(i, _)| rule_of_column[*i].name.starts_with("departure"))
-            .map(|(_, v)| *v)
-            .collect()
+            .filter(|ticket| translator.ticket_is_valid(ticket))
+            .count();
+        let expected = tickets
+            .iter()
+            .filter(|ticket| {
+                ticket
+                    .iter()
+                    .all(|&num| translator.rules.iter().any(|rule| rule.check(num)))
+            })
+            .count();
+        assert_eq!(valid, expected);
+    }
+
+    // Rule `i` accepts 10 * i to 10 * i + 14, the tickets only use the first ten of these.
+    // Column `c` holds the field `(c * 3 + 1) % fields`.
+    fn noisy_translator(fields: usize, tickets: usize) -> TicketTranslator {
+        let rules: Vec<String> = (0..fields)
+            .map(|i| format!("field {}: {}-{}", i, 10 * i, 10 * i + 14))
+            .collect();
+        let rules: Vec<&str> = rules.iter().map(String::as_str).collect();
+
+        let mut state = 7u64;
+        let tickets: Vec<Vec<usize>> = (0..tickets)
+            .map(|_| {
+                (0..fields)
+                    .map(|column| 10 * ((column * 3 + 1) % fields) + random(&mut state) % 10)
+                    .collect()
+            })
+            .collect();
+        let tickets: Vec<&[usize]> = tickets.iter().map(Vec::as_slice).collect();
+        from_parts(&rules, &tickets)
+    }
+
+    #[test]
+    fn tolerant_assignment() {
+        let mut translator = noisy_translator(5, 40);
+        let expected: Vec<usize> = (0..5).map(|column| (column * 3 + 1) % 5).collect();
+        let fields = |assignment: &TolerantAssignment| -> Vec<usize> {
+            assignment
+                .fields
+                .iter()
+                .map(|field| field.rule.name()["field ".len()..].parse().unwrap())
+                .collect()
+        };
+
+        let clean = translator.assign_tolerant().unwrap();
+        assert_eq!(fields(&clean), expected);
+        assert!(clean.fields.iter().all(|field| field.confidence == 1.0));
+        assert_eq!(clean.total_support, 5 * 40);
+        assert!(matches!(translator.assign(1), Assignment::Unique(_)));
+
+        // A value of the field in column 4 ended up in column 0 of one ticket
+        translator.other_tickets[3][0] = 10 * expected[4] + 2;
+        assert!(!matches!(translator.assign(1), Assignment::Unique(_)));
+
+        let noisy = translator.assign_tolerant().unwrap();
+        assert_eq!(fields(&noisy), expected);
+        assert_eq!(noisy.total_support, 5 * 40 - 1);
+        assert_eq!(noisy.fields[0].support, 39);
+        assert_eq!(noisy.fields[0].confidence, 39.0 / 40.0);
+        assert!(noisy.fields[1..]
+            .iter()
+            .all(|field| field.confidence == 1.0));
+        assert!(noisy.fields.iter().all(|field| field.margin > Some(0)));
+
+        // Fields that fit the same columns equally well have no margin
+        let translator = from_parts(&["a: 1-10", "b: 1-10"], &[&[1, 2], &[3, 4]]);
+        let tied = translator.assign_tolerant().unwrap();
+        assert!(tied.fields.iter().all(|field| field.margin == Some(0)));
+
+        // Without a valid ticket nothing supports any field
+        let translator = from_parts(&["a: 1-10", "b: 1-10"], &[&[50, 60], &[70, 80]]);
+        let unsupported = translator.assign_tolerant().unwrap();
+        assert_eq!(unsupported.total_support, 0);
+        assert!(unsupported
+            .fields
+            .iter()
+            .all(|field| field.support == 0 && field.confidence == 0.0));
+
+        let translator = from_parts(&["a: 1-10"], &[&[1, 2]]);
+        assert_eq!(translator.assign_tolerant(), None);
+
+        // Agrees with the exact assignment on clean data
+        let translator = TicketTranslator::new("input").unwrap();
+        let tolerant: Vec<&Rule> = translator
+            .assign_tolerant()
+            .unwrap()
+            .fields
+            .iter()
+            .map(|field| field.rule)
+            .collect();
+        assert_eq!(Some(tolerant), translator.translate());
     }
 }
@@ -0,0 +1,226 @@
+use crate::{Rule, TicketTranslator};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::str::FromStr;
+
+const OWN_TICKET_HEADER: &str = "your ticket:";
+const NEARBY_TICKETS_HEADER: &str = "nearby tickets:";
+
+// `line` is the 1-based line of the input where parsing stopped
+#[derive(Debug)]
+pub enum ParseError {
+    Io(io::Error),
+    InvalidRule {
+        line: usize,
+        reason: String,
+    },
+    MissingHeader {
+        line: usize,
+        expected: &'static str,
+    },
+    MissingOwnTicket {
+        line: usize,
+    },
+    InvalidNumber {
+        line: usize,
+        found: String,
+    },
+    WidthMismatch {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Io(error) => write!(f, "couldn't read tickets: {}", error),
+            ParseError::InvalidRule { line, reason } => {
+                write!(f, "invalid rule in line {}: {}", line, reason)
+            }
+            ParseError::MissingHeader { line, expected } => {
+                write!(f, "expected {:?} in line {}", expected, line)
+            }
+            ParseError::MissingOwnTicket { line } => {
+                write!(f, "expected our own ticket in line {}", line)
+            }
+            ParseError::InvalidNumber { line, found } => {
+                write!(f, "invalid number {:?} in line {}", found, line)
+            }
+            ParseError::WidthMismatch {
+                line,
+                expected,
+                found,
+            } => write!(
+                f,
+                "ticket in line {} has {} values, but our own ticket has {}",
+                line, found, expected
+            ),
+        }
+    }
+}
+
+impl Error for ParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ParseError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ParseError {
+    fn from(error: io::Error) -> Self {
+        ParseError::Io(error)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Section {
+    Rules,
+    OwnTicket,
+    BeforeNearbyTickets,
+    NearbyTickets,
+}
+
+// Takes the input one line at a time, so that it works for strings and readers alike.
+// Empty lines are allowed anywhere between the sections.
+struct Parser {
+    section: Section,
+    line: usize,
+    rules: Vec<Rule>,
+    own_ticket: Vec<usize>,
+    other_tickets: Vec<Vec<usize>>,
+    nearby_lines: Vec<usize>,
+}
+
+impl Parser {
+    fn new() -> Self {
+        Self {
+            section: Section::Rules,
+            line: 0,
+            rules: Vec::new(),
+            own_ticket: Vec::new(),
+            other_tickets: Vec::new(),
+            nearby_lines: Vec::new(),
+        }
+    }
+
+    fn feed(&mut self, line: &str) -> Result<(), ParseError> {
+        self.line += 1;
+        if line.is_empty() {
+            return Ok(());
+        }
+
+        match self.section {
+            Section::Rules if line == OWN_TICKET_HEADER => self.section = Section::OwnTicket,
+            Section::Rules if line == NEARBY_TICKETS_HEADER => {
+                return Err(ParseError::MissingHeader {
+                    line: self.line,
+                    expected: OWN_TICKET_HEADER,
+                })
+            }
+            Section::Rules => {
+                let rule =
+                    Rule::try_from(line.to_owned()).map_err(|error| ParseError::InvalidRule {
+                        line: self.line,
+                        reason: error.to_string(),
+                    })?;
+                self.rules.push(rule);
+            }
+            Section::OwnTicket if line == NEARBY_TICKETS_HEADER => {
+                return Err(ParseError::MissingOwnTicket { line: self.line })
+            }
+            Section::OwnTicket => {
+                self.own_ticket = self.ticket(line)?;
+                self.section = Section::BeforeNearbyTickets;
+            }
+            Section::BeforeNearbyTickets if line == NEARBY_TICKETS_HEADER => {
+                self.section = Section::NearbyTickets
+            }
+            Section::BeforeNearbyTickets => {
+                return Err(ParseError::MissingHeader {
+                    line: self.line,
+                    expected: NEARBY_TICKETS_HEADER,
+                })
+            }
+            Section::NearbyTickets => {
+                let ticket = self.ticket(line)?;
+                if ticket.len() != self.own_ticket.len() {
+                    return Err(ParseError::WidthMismatch {
+                        line: self.line,
+                        expected: self.own_ticket.len(),
+                        found: ticket.len(),
+                    });
+                }
+                self.other_tickets.push(ticket);
+                self.nearby_lines.push(self.line);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn ticket(&self, line: &str) -> Result<Vec<usize>, ParseError> {
+        line.split(',')
+            .map(|num| {
+                num.trim().parse().map_err(|_| ParseError::InvalidNumber {
+                    line: self.line,
+                    found: num.to_owned(),
+                })
+            })
+            .collect()
+    }
+
+    fn finish(self) -> Result<TicketTranslator, ParseError> {
+        // The error points right behind the last line
+        let line = self.line + 1;
+        match self.section {
+            Section::Rules => Err(ParseError::MissingHeader {
+                line,
+                expected: OWN_TICKET_HEADER,
+            }),
+            Section::OwnTicket => Err(ParseError::MissingOwnTicket { line }),
+            Section::BeforeNearbyTickets => Err(ParseError::MissingHeader {
+                line,
+                expected: NEARBY_TICKETS_HEADER,
+            }),
+            Section::NearbyTickets => Ok(TicketTranslator::from_parts(
+                self.rules,
+                self.own_ticket,
+                self.other_tickets,
+                self.nearby_lines,
+            )),
+        }
+    }
+}
+
+impl FromStr for TicketTranslator {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser::new();
+        for line in s.lines() {
+            parser.feed(line)?;
+        }
+        parser.finish()
+    }
+}
+
+impl TicketTranslator {
+    pub fn new(filename: &str) -> Result<Self, ParseError> {
+        Self::from_reader(BufReader::new(File::open(filename)?))
+    }
+
+    pub fn from_reader<R: BufRead>(reader: R) -> Result<Self, ParseError> {
+        let mut parser = Parser::new();
+        for line in reader.lines() {
+            parser.feed(&line?)?;
+        }
+        parser.finish()
+    }
+}
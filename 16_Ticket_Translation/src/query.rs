@@ -0,0 +1,110 @@
+use crate::records::DecodedTickets;
+use crate::TicketTranslator;
+
+// Picks fields by their name. Patterns match the whole name, `*` matches any
+// number of characters, so `arrival*` finds every arrival field.
+#[derive(Clone, Copy)]
+pub enum Fields<'a> {
+    Pattern(&'a str),
+    Predicate(&'a dyn Fn(&str) -> bool),
+}
+
+impl Fields<'_> {
+    pub fn matches(&self, name: &str) -> bool {
+        match self {
+            Fields::Pattern(pattern) => glob_matches(pattern, name),
+            Fields::Predicate(predicate) => predicate(name),
+        }
+    }
+}
+
+fn glob_matches(pattern: &str, name: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or("");
+    let mut rest = match name.strip_prefix(first) {
+        Some(rest) => rest,
+        None => return false,
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    match parts.split_last() {
+        // No `*` at all
+        None => rest.is_empty(),
+        Some((last, middle)) => {
+            for part in middle {
+                match rest.find(part) {
+                    Some(index) => rest = &rest[index + part.len()..],
+                    None => return false,
+                }
+            }
+            rest.ends_with(last)
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum Tickets {
+    Own,
+    // Our own ticket and every valid nearby ticket
+    AllValid,
+}
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum Aggregate {
+    Sum,
+    Product,
+    Min,
+    Max,
+    List,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub enum QueryResult {
+    // `None` for the minimum or maximum of no values, or if the result overflows
+    Number(Option<usize>),
+    List(Vec<usize>),
+}
+
+impl Aggregate {
+    pub fn apply(self, values: Vec<usize>) -> QueryResult {
+        let mut values = values.into_iter();
+        match self {
+            Aggregate::Sum => QueryResult::Number(values.try_fold(0, usize::checked_add)),
+            Aggregate::Product => QueryResult::Number(values.try_fold(1, usize::checked_mul)),
+            Aggregate::Min => QueryResult::Number(values.min()),
+            Aggregate::Max => QueryResult::Number(values.max()),
+            Aggregate::List => QueryResult::List(values.collect()),
+        }
+    }
+}
+
+impl DecodedTickets<'_> {
+    // The values of the matching fields, ticket by ticket and in column order
+    pub fn select(&self, fields: Fields, tickets: Tickets) -> Vec<usize> {
+        let records: Box<dyn Iterator<Item = _>> = match tickets {
+            Tickets::Own => Box::new(std::iter::once(&self.own_ticket)),
+            Tickets::AllValid => Box::new(self.records()),
+        };
+        records
+            .flat_map(|record| record.fields.iter())
+            .filter(|(name, _)| fields.matches(name))
+            .map(|&(_, value)| value)
+            .collect()
+    }
+
+    pub fn query(&self, fields: Fields, tickets: Tickets, aggregate: Aggregate) -> QueryResult {
+        aggregate.apply(self.select(fields, tickets))
+    }
+}
+
+impl TicketTranslator {
+    // Only answers if the tickets determine the field of every column unambiguously
+    pub fn query(
+        &self,
+        fields: Fields,
+        tickets: Tickets,
+        aggregate: Aggregate,
+    ) -> Option<QueryResult> {
+        Some(self.decode()?.query(fields, tickets, aggregate))
+    }
+}
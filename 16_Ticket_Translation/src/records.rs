@@ -0,0 +1,113 @@
+use crate::{Rule, TicketTranslator};
+use std::io::{self, Write};
+
+// The values of a ticket together with the names of their fields, in column order
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct TicketRecord<'r> {
+    pub fields: Vec<(&'r str, usize)>,
+}
+
+impl TicketRecord<'_> {
+    pub fn get(&self, name: &str) -> Option<usize> {
+        self.fields
+            .iter()
+            .find(|(field, _)| *field == name)
+            .map(|&(_, value)| value)
+    }
+}
+
+// Our own ticket and every valid nearby ticket, in the order of the input
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct DecodedTickets<'r> {
+    pub own_ticket: TicketRecord<'r>,
+    pub nearby_tickets: Vec<TicketRecord<'r>>,
+}
+
+impl<'r> DecodedTickets<'r> {
+    // Our own ticket comes first
+    pub fn records(&self) -> impl Iterator<Item = &TicketRecord<'r>> {
+        std::iter::once(&self.own_ticket).chain(&self.nearby_tickets)
+    }
+
+    // One JSON object per line and ticket, the keys are in column order
+    pub fn write_json_lines<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        for record in self.records() {
+            write!(out, "{{")?;
+            for (i, (name, value)) in record.fields.iter().enumerate() {
+                let separator = if i == 0 { "" } else { "," };
+                write!(out, "{}\"{}\":{}", separator, escape_json(name), value)?;
+            }
+            writeln!(out, "}}")?;
+        }
+        Ok(())
+    }
+
+    // The header row holds the field names, every following row is a ticket
+    pub fn write_csv<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let header: Vec<String> = self
+            .own_ticket
+            .fields
+            .iter()
+            .map(|(name, _)| escape_csv(name))
+            .collect();
+        writeln!(out, "{}", header.join(","))?;
+        for record in self.records() {
+            let row: Vec<String> = record
+                .fields
+                .iter()
+                .map(|(_, value)| value.to_string())
+                .collect();
+            writeln!(out, "{}", row.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+fn escape_json(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn escape_csv(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_owned()
+    }
+}
+
+impl TicketTranslator {
+    // Only works if the tickets determine the field of every column unambiguously
+    pub fn decode(&self) -> Option<DecodedTickets<'_>> {
+        Some(self.decode_with(&self.translate()?))
+    }
+
+    // Decodes with any rule for each column, for example one of several possible assignments
+    pub fn decode_with<'r>(&'r self, rule_of_column: &[&'r Rule]) -> DecodedTickets<'r> {
+        let record = |ticket: &[usize]| TicketRecord {
+            fields: rule_of_column
+                .iter()
+                .map(|rule| rule.name())
+                .zip(ticket.iter().copied())
+                .collect(),
+        };
+
+        DecodedTickets {
+            own_ticket: record(&self.own_ticket),
+            nearby_tickets: self
+                .other_tickets
+                .iter()
+                .filter(|ticket| self.ticket_is_valid(ticket))
+                .map(|ticket| record(ticket))
+                .collect(),
+        }
+    }
+}
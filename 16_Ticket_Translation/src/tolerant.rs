@@ -0,0 +1,183 @@
+use crate::{Rule, TicketTranslator};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldAssignment<'r> {
+    pub rule: &'r Rule,
+    pub column: usize,
+    // Number of valid nearby tickets whose value in the column matches the rule
+    pub support: usize,
+    // Share of the valid nearby tickets that agree, from 0 to 1, or 0 if none is valid
+    pub confidence: f64,
+    // How much support the best assignment loses if the field can't have this column,
+    // `None` if there is no other column to give it
+    pub margin: Option<usize>,
+}
+
+// The fields in column order
+#[derive(Clone, Debug, PartialEq)]
+pub struct TolerantAssignment<'r> {
+    pub fields: Vec<FieldAssignment<'r>>,
+    pub total_support: usize,
+}
+
+// Assignment of rows to columns with the least total cost (Hungarian algorithm
+// with potentials), `cost` has to be square
+fn min_cost_assignment(cost: &[Vec<i64>]) -> Vec<usize> {
+    let n = cost.len();
+    // Index 0 is a virtual row and column, the real ones start at 1
+    let mut u = vec![0; n + 1];
+    let mut v = vec![0; n + 1];
+    let mut row_of = vec![0; n + 1];
+    let mut way = vec![0; n + 1];
+
+    for row in 1..=n {
+        row_of[0] = row;
+        let mut column = 0;
+        let mut min_to = vec![i64::MAX; n + 1];
+        let mut used = vec![false; n + 1];
+        loop {
+            used[column] = true;
+            let current = row_of[column];
+            let mut delta = i64::MAX;
+            let mut next = 0;
+            for j in 1..=n {
+                if used[j] {
+                    continue;
+                }
+                let reduced = cost[current - 1][j - 1] - u[current] - v[j];
+                if reduced < min_to[j] {
+                    min_to[j] = reduced;
+                    way[j] = column;
+                }
+                if min_to[j] < delta {
+                    delta = min_to[j];
+                    next = j;
+                }
+            }
+            for j in 0..=n {
+                if used[j] {
+                    u[row_of[j]] += delta;
+                    v[j] -= delta;
+                } else {
+                    min_to[j] -= delta;
+                }
+            }
+            column = next;
+            if row_of[column] == 0 {
+                break;
+            }
+        }
+        // Flip the augmenting path
+        while column != 0 {
+            let previous = way[column];
+            row_of[column] = row_of[previous];
+            column = previous;
+        }
+    }
+
+    let mut column_of = vec![0; n];
+    for j in 1..=n {
+        column_of[row_of[j] - 1] = j - 1;
+    }
+    column_of
+}
+
+fn total(support: &[Vec<usize>], column_of: &[usize]) -> usize {
+    column_of
+        .iter()
+        .enumerate()
+        .map(|(rule, &column)| support[rule][column])
+        .sum()
+}
+
+impl TicketTranslator {
+    // `support[rule][column]` counts the valid nearby tickets that agree with the pair
+    fn support_matrix(&self) -> (Vec<Vec<usize>>, usize) {
+        let valid_tickets: Vec<&Vec<usize>> = self
+            .other_tickets
+            .iter()
+            .filter(|ticket| self.ticket_is_valid(ticket))
+            .collect();
+
+        let support = self
+            .rules
+            .iter()
+            .map(|rule| {
+                (0..self.own_ticket.len())
+                    .map(|column| {
+                        valid_tickets
+                            .iter()
+                            .filter(|ticket| rule.check(ticket[column]))
+                            .count()
+                    })
+                    .collect()
+            })
+            .collect();
+        (support, valid_tickets.len())
+    }
+
+    // Unlike `assign`, a single mis-scanned value doesn't rule out a column. Instead the
+    // assignment that the most tickets agree with wins. Needs as many rules as columns.
+    pub fn assign_tolerant(&self) -> Option<TolerantAssignment<'_>> {
+        if self.rules.len() != self.own_ticket.len() {
+            return None;
+        }
+
+        let (support, tickets) = self.support_matrix();
+        // Every pair costs what it lacks in support, forbidden pairs cost more than any
+        // complete assignment without them
+        let cost = |forbidden: Option<(usize, usize)>| -> Vec<Vec<i64>> {
+            let forbidden_cost = (tickets as i64 + 1) * (self.rules.len() as i64 + 1);
+            support
+                .iter()
+                .enumerate()
+                .map(|(rule, row)| {
+                    row.iter()
+                        .enumerate()
+                        .map(|(column, &support)| {
+                            if forbidden == Some((rule, column)) {
+                                forbidden_cost
+                            } else {
+                                (tickets - support) as i64
+                            }
+                        })
+                        .collect()
+                })
+                .collect()
+        };
+
+        let column_of = min_cost_assignment(&cost(None));
+        let total_support = total(&support, &column_of);
+
+        let mut fields: Vec<FieldAssignment> = column_of
+            .iter()
+            .enumerate()
+            .map(|(rule, &column)| {
+                let margin = if self.rules.len() > 1 {
+                    let alternative = min_cost_assignment(&cost(Some((rule, column))));
+                    Some(total_support - total(&support, &alternative))
+                } else {
+                    None
+                };
+
+                FieldAssignment {
+                    rule: &self.rules[rule],
+                    column,
+                    support: support[rule][column],
+                    confidence: if tickets == 0 {
+                        0.0
+                    } else {
+                        support[rule][column] as f64 / tickets as f64
+                    },
+                    margin,
+                }
+            })
+            .collect();
+        fields.sort_by_key(|field| field.column);
+
+        Some(TolerantAssignment {
+            fields,
+            total_support,
+        })
+    }
+}
@@ -0,0 +1,211 @@
+use crate::{Rule, TicketTranslator};
+use std::fmt;
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct NearMiss<'r> {
+    pub rule: &'r Rule,
+    pub miss: Miss,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub enum Miss {
+    // The value lies `distance` (at least one) outside of the range
+    Outside {
+        range: RangeInclusive<usize>,
+        distance: usize,
+    },
+    // The rule would accept the value if it weren't for this exclusion
+    Excluded(RangeInclusive<usize>),
+}
+
+impl fmt::Display for NearMiss<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.miss {
+            Miss::Outside { range, .. } => {
+                write!(f, "{} {}", self.rule.name(), DisplayRange(range))
+            }
+            Miss::Excluded(range) => write!(f, "{} not {}", self.rule.name(), DisplayRange(range)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct InvalidValue<'r> {
+    pub column: usize,
+    pub value: usize,
+    // Every exclusion that rejected the value, or if there is none,
+    // all ranges that are closest to the value as there can be ties
+    pub nearest: Vec<NearMiss<'r>>,
+}
+
+// Tickets and columns are counted from zero, in the order of the nearby tickets.
+// `line` is the 1-based line of the input that the ticket was read from.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct TicketReport<'r> {
+    pub ticket: usize,
+    pub line: usize,
+    pub invalid: Vec<InvalidValue<'r>>,
+}
+
+// Only lists the tickets that have invalid values
+#[derive(Clone, Debug, Default, Hash, PartialEq, Eq)]
+pub struct ValidationReport<'r> {
+    pub tickets: Vec<TicketReport<'r>>,
+}
+
+impl ValidationReport<'_> {
+    pub fn is_valid(&self) -> bool {
+        self.tickets.is_empty()
+    }
+
+    pub fn invalid_values(&self) -> usize {
+        self.tickets.iter().map(|report| report.invalid.len()).sum()
+    }
+
+    // One row per invalid value, the near misses are joined by `; `
+    pub fn write_csv<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(out, "ticket,line,column,value,nearest")?;
+        for report in &self.tickets {
+            for invalid in &report.invalid {
+                let nearest: Vec<String> =
+                    invalid.nearest.iter().map(NearMiss::to_string).collect();
+                writeln!(
+                    out,
+                    "{},{},{},{},\"{}\"",
+                    report.ticket,
+                    report.line,
+                    invalid.column,
+                    invalid.value,
+                    nearest.join("; ").replace('"', "\"\"")
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ValidationReport<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for report in &self.tickets {
+            writeln!(f, "ticket {} (line {}):", report.ticket, report.line)?;
+            for invalid in &report.invalid {
+                write!(
+                    f,
+                    "    column {}: {} matches no rule",
+                    invalid.column, invalid.value
+                )?;
+                for (i, near) in invalid.nearest.iter().enumerate() {
+                    let separator = if i == 0 { ", nearest" } else { "," };
+                    match &near.miss {
+                        Miss::Outside { distance, .. } => {
+                            write!(f, "{} {} (off by {})", separator, near, distance)?
+                        }
+                        Miss::Excluded(_) => write!(f, "{} {} (excluded)", separator, near)?,
+                    }
+                }
+                writeln!(f)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Writes ranges the way rules spell them
+struct DisplayRange<'a>(&'a RangeInclusive<usize>);
+
+impl fmt::Display for DisplayRange<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (start, end) = (*self.0.start(), *self.0.end());
+        if start == end {
+            write!(f, "{}", start)
+        } else if end == usize::MAX {
+            write!(f, ">= {}", start)
+        } else {
+            write!(f, "{}-{}", start, end)
+        }
+    }
+}
+
+fn distance(range: &RangeInclusive<usize>, value: usize) -> usize {
+    if value < *range.start() {
+        range.start() - value
+    } else {
+        value.saturating_sub(*range.end())
+    }
+}
+
+impl TicketTranslator {
+    pub fn validate(&self) -> ValidationReport<'_> {
+        let tickets = self
+            .other_tickets
+            .iter()
+            .zip(&self.nearby_lines)
+            .enumerate()
+            .map(|(ticket, (values, &line))| TicketReport {
+                ticket,
+                line,
+                invalid: self.invalid_values(values),
+            })
+            .filter(|report| !report.invalid.is_empty())
+            .collect();
+        ValidationReport { tickets }
+    }
+
+    pub fn invalid_values(&self, ticket: &[usize]) -> Vec<InvalidValue<'_>> {
+        ticket
+            .iter()
+            .copied()
+            .enumerate()
+            .filter(|&(_, value)| !self.accepts(value))
+            .map(|(column, value)| InvalidValue {
+                column,
+                value,
+                nearest: self.nearest_ranges(value),
+            })
+            .collect()
+    }
+
+    fn nearest_ranges(&self, value: usize) -> Vec<NearMiss<'_>> {
+        // Rules that only reject the value because of an exclusion come first
+        let excluded: Vec<NearMiss> = self
+            .rules
+            .iter()
+            .filter(|rule| {
+                rule.ranges().is_empty() || rule.ranges().iter().any(|range| range.contains(&value))
+            })
+            .flat_map(|rule| {
+                rule.exclusions()
+                    .iter()
+                    .filter(move |range| range.contains(&value))
+                    .map(move |range| NearMiss {
+                        rule,
+                        miss: Miss::Excluded(range.clone()),
+                    })
+            })
+            .collect();
+        if !excluded.is_empty() {
+            return excluded;
+        }
+
+        let outside: Vec<(&Rule, &RangeInclusive<usize>, usize)> = self
+            .rules
+            .iter()
+            .flat_map(|rule| rule.ranges().iter().map(move |range| (rule, range)))
+            .map(|(rule, range)| (rule, range, distance(range, value)))
+            .collect();
+        let closest = outside.iter().map(|&(_, _, distance)| distance).min();
+        outside
+            .into_iter()
+            .filter(|&(_, _, distance)| Some(distance) == closest)
+            .map(|(rule, range, distance)| NearMiss {
+                rule,
+                miss: Miss::Outside {
+                    range: range.clone(),
+                    distance,
+                },
+            })
+            .collect()
+    }
+}